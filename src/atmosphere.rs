@@ -0,0 +1,61 @@
+use crate::mesh::Mesh;
+use crate::sphere;
+use crate::uniform_buffer::{AtmosphereUniform, UniformBuffer};
+use cgmath::Vector3;
+
+/// How many times the shell an atmosphere is drawn on is subdivided
+const SHELL_SUBDIVISIONS: u32 = 4;
+
+/// The shell is a little larger than the atmosphere, so its flat faces don't cut off
+/// the edge of the atmosphere
+const SHELL_MARGIN: f32 = 1.01;
+
+/// Air around a body, which scatters the light of the stars
+#[derive(Copy, Clone, Debug)]
+pub struct AtmosphereConfig {
+    /// How far the atmosphere reaches above the surface
+    pub height: f32,
+    /// How much of each colour is scattered by the air per unit distance, at the surface.
+    /// Blue is scattered the most, which makes the limbs blue and sunsets red.
+    pub rayleigh: Vector3<f32>,
+    /// The height over which the air thins out by a factor of e
+    pub rayleigh_scale_height: f32,
+    /// How much light is scattered by haze per unit distance, at the surface. Every
+    /// colour is scattered alike, mostly forwards, so there is a glow around the stars.
+    pub mie: f32,
+    pub mie_scale_height: f32,
+    /// How much the haze scatters light forwards (towards 1) rather than backwards
+    /// (towards -1)
+    pub mie_anisotropy: f32,
+}
+
+/// The shell an atmosphere is drawn on. The scattering is ray marched through the shell
+/// for every pixel it covers.
+pub struct Atmosphere {
+    pub config: AtmosphereConfig,
+    pub mesh: Mesh,
+    pub uniform_buffer: UniformBuffer<AtmosphereUniform>,
+}
+
+impl Atmosphere {
+    pub fn new(config: AtmosphereConfig, surface_radius: f32, device: &wgpu::Device) -> Self {
+        let atmosphere_radius = surface_radius + config.height;
+        let shell_radius = atmosphere_radius * SHELL_MARGIN;
+
+        let (vertices, indices) = sphere::build_icosphere(shell_radius, SHELL_SUBDIVISIONS);
+        let mesh = Mesh::new(vertices, indices, device);
+
+        let uniform_buffer = UniformBuffer::new(
+            "Atmosphere Uniform Buffer",
+            wgpu::ShaderStage::FRAGMENT,
+            AtmosphereUniform::new(&config, surface_radius, atmosphere_radius, shell_radius),
+            device,
+        );
+
+        Self {
+            config,
+            mesh,
+            uniform_buffer,
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::mesh::Mesh;
+use crate::noise::Random;
+use crate::sphere;
+use crate::texture::Texture;
+use crate::utils::Vertex;
+use anyhow::*;
+use cgmath::{InnerSpace, Vector2, Vector3};
+use std::path::Path;
+
+/// How many stars are scattered across the sky
+const STAR_COUNT: u32 = 6000;
+const STAR_SEED: u32 = 42;
+
+/// The apparent magnitudes of the brightest and faintest stars. Smaller magnitudes are
+/// brighter, and there are far more faint stars than bright ones.
+const BRIGHTEST_MAGNITUDE: f32 = -1.5;
+const FAINTEST_MAGNITUDE: f32 = 6.5;
+
+/// How bright the faintest stars are drawn
+const FAINTEST_INTENSITY: f32 = 0.3;
+
+/// The angular size (in radians) of the faintest stars
+const FAINTEST_SIZE: f32 = 0.002;
+
+/// The range of surface temperatures (in kelvin) of the stars
+const MIN_TEMPERATURE: f32 = 3000.0;
+const MAX_TEMPERATURE: f32 = 25000.0;
+
+/// If this directory holds the six faces of a cube map, the sky is drawn with them instead
+/// of the starfield
+const SKYBOX_DIRECTORY: &str = "skybox";
+
+/// The names of the faces of a skybox, in the order of the layers of a cube map
+const SKYBOX_FACES: [&str; 6] = ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"];
+
+/// What is drawn behind the bodies
+pub enum Background {
+    /// Stars from a fixed seed, drawn as soft points
+    Starfield(Mesh),
+    /// A cube map, loaded from images
+    Skybox { mesh: Mesh, texture: Texture },
+}
+
+impl Background {
+    /// Use the skybox if one has been provided, otherwise scatter stars across the sky
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let directory = Path::new(SKYBOX_DIRECTORY);
+        if directory.is_dir() {
+            match Self::load_skybox(directory, device, queue) {
+                Ok(background) => return background,
+                Err(e) => log::error!("Failed to load the skybox: {:?}", e),
+            }
+        }
+
+        let (vertices, indices) = build_starfield(STAR_COUNT, STAR_SEED);
+        Background::Starfield(Mesh::new(vertices, indices, device))
+    }
+
+    /// Load the six faces of a skybox from a directory
+    pub fn load_skybox(
+        directory: &Path,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self> {
+        let faces = SKYBOX_FACES
+            .iter()
+            .map(|face| {
+                let path = directory.join(face);
+                image::open(&path).with_context(|| format!("Failed to open {}", path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let texture = Texture::from_cube_images(device, queue, &faces, "skybox")?;
+
+        // Each point of the mesh is the direction the cube map is sampled in
+        let (vertices, indices) = sphere::build_cube_sphere(1.0, 1);
+
+        Ok(Background::Skybox {
+            mesh: Mesh::new(vertices, indices, device),
+            texture,
+        })
+    }
+}
+
+/// Build a quad facing the centre of the sky for each star. The position of each vertex
+/// is a direction from the camera, so the stars are drawn at an infinite distance.
+pub fn build_starfield(count: u32, seed: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let mut random = Random::new(seed);
+
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(count as usize * 4);
+    let mut indices: Vec<u32> = Vec::with_capacity(count as usize * 6);
+    for _ in 0..count {
+        let direction = random.direction();
+
+        // The number of stars grows tenfold every two magnitudes
+        let fraction = 1.0 - random.next_f32();
+        let magnitude = (FAINTEST_MAGNITUDE + 2.0 * fraction.log10()).max(BRIGHTEST_MAGNITUDE);
+
+        // How much more light the star gives than the faintest stars
+        let brightness = 10.0_f32.powf(-0.4 * (magnitude - FAINTEST_MAGNITUDE));
+        let intensity = (FAINTEST_INTENSITY * brightness.powf(0.25)).min(1.0);
+        let size = FAINTEST_SIZE * brightness.powf(0.15);
+
+        // Most stars are cool and red, few are hot and blue
+        let temperature =
+            MIN_TEMPERATURE + (MAX_TEMPERATURE - MIN_TEMPERATURE) * random.next_f32().powi(3);
+        let color = star_color(temperature) * intensity;
+
+        // Two directions across the face of the quad
+        let axis = if direction.y.abs() < 0.9 {
+            Vector3::unit_y()
+        } else {
+            Vector3::unit_x()
+        };
+        let right = direction.cross(axis).normalize() * size;
+        let up = right.cross(direction);
+
+        let offset = vertices.len() as u32;
+        for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter() {
+            let position = direction + right * (x * 2.0 - 1.0) + up * (y * 2.0 - 1.0);
+            let mut vertex = Vertex::with_color(position, color);
+            vertex.tex_coord = Vector2::new(*x, *y);
+            vertices.push(vertex);
+        }
+
+        indices.extend_from_slice(&[
+            offset,
+            offset + 1,
+            offset + 2,
+            offset,
+            offset + 2,
+            offset + 3,
+        ]);
+    }
+
+    (vertices, indices)
+}
+
+/// The colour of a black body at a temperature (in kelvin), scaled so its brightest
+/// channel is 1
+fn star_color(temperature: f32) -> Vector3<f32> {
+    let t = temperature / 100.0;
+
+    let red = if t <= 66.0 {
+        1.0
+    } else {
+        1.292_936 * (t - 60.0).powf(-0.133_204_76)
+    };
+
+    let green = if t <= 66.0 {
+        0.390_081_6 * t.ln() - 0.631_841_4
+    } else {
+        1.129_890_9 * (t - 60.0).powf(-0.075_514_85)
+    };
+
+    let blue = if t >= 66.0 {
+        1.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        0.543_206_8 * (t - 10.0).ln() - 1.196_254_1
+    };
+
+    Vector3::new(
+        red.clamp(0.0, 1.0),
+        green.clamp(0.0, 1.0),
+        blue.clamp(0.0, 1.0),
+    )
+}
@@ -0,0 +1,221 @@
+use crate::c_body::{
+    self, MATERIAL_FOREST, MATERIAL_GRASS, MATERIAL_ICE, MATERIAL_ROCK, MATERIAL_SAND,
+    MATERIAL_TUNDRA, MATERIAL_WATER,
+};
+use crate::noise::Noise;
+use crate::sphere;
+use cgmath::{Vector2, Vector3};
+
+/// The distance from a star where the surface of a body is, on average, temperate
+pub const HABITABLE_DISTANCE: f32 = 200.0;
+
+/// The average temperature (in kelvin) of a body at the habitable distance
+const HABITABLE_TEMPERATURE: f32 = 288.0;
+
+/// How much warmer the equator is than the average, and how much colder the poles are
+/// than the equator
+const EQUATOR_WARMING: f32 = 25.0;
+const POLAR_COOLING: f32 = 70.0;
+
+/// How much colder it gets with altitude, per radius of the body
+const LAPSE_RATE: f32 = 300.0;
+
+/// Below these temperatures the surface is frozen
+const ICE_TEMPERATURE: f32 = 250.0;
+const TUNDRA_TEMPERATURE: f32 = 268.0;
+
+/// Deserts only form when it's both hot and dry
+const DESERT_TEMPERATURE: f32 = 285.0;
+const DESERT_MOISTURE: f32 = 0.35;
+const FOREST_MOISTURE: f32 = 0.6;
+
+/// Altitudes (relative to the radius of the body) of mountains and beaches
+const MOUNTAIN_ALTITUDE: f32 = 0.12;
+const BEACH_ALTITUDE: f32 = 0.02;
+
+/// How many patches of wet and dry land there are around the body
+const MOISTURE_FREQUENCY: f32 = 2.5;
+const MOISTURE_OCTAVES: u32 = 3;
+
+/// How deep (in voxels) the surface materials of a biome go, below this is the material
+/// of the body generator
+pub const BIOME_DEPTH: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Biome {
+    /// Below sea level
+    Ocean,
+    Beach,
+    Desert,
+    Grassland,
+    Forest,
+    Tundra,
+    Mountain,
+    /// Polar ice caps and snow on high peaks
+    Ice,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 8] = [
+        Biome::Ocean,
+        Biome::Beach,
+        Biome::Desert,
+        Biome::Grassland,
+        Biome::Forest,
+        Biome::Tundra,
+        Biome::Mountain,
+        Biome::Ice,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::Ocean => "Ocean",
+            Biome::Beach => "Beach",
+            Biome::Desert => "Desert",
+            Biome::Grassland => "Grassland",
+            Biome::Forest => "Forest",
+            Biome::Tundra => "Tundra",
+            Biome::Mountain => "Mountain",
+            Biome::Ice => "Ice",
+        }
+    }
+
+    /// The material that the surface of this biome is made of
+    pub fn material(&self) -> u8 {
+        match self {
+            Biome::Ocean | Biome::Beach | Biome::Desert => MATERIAL_SAND,
+            Biome::Grassland => MATERIAL_GRASS,
+            Biome::Forest => MATERIAL_FOREST,
+            Biome::Tundra => MATERIAL_TUNDRA,
+            Biome::Mountain => MATERIAL_ROCK,
+            Biome::Ice => MATERIAL_ICE,
+        }
+    }
+
+    /// The colour of this biome on a biome map
+    pub fn color(&self) -> Vector3<f32> {
+        match self {
+            Biome::Ocean => c_body::material_color(MATERIAL_WATER),
+            _ => c_body::material_color(self.material()),
+        }
+    }
+}
+
+/// Configuration for the biomes of a body
+#[derive(Copy, Clone, Debug)]
+pub struct BiomeConfig {
+    /// Seeds the moisture noise
+    pub seed: u32,
+    /// How far the body is from the star that warms it
+    pub star_distance: f32,
+}
+
+/// Assigns biomes to the surface of a body from its latitude, altitude, moisture and how
+/// far it is from its star
+#[derive(Clone)]
+pub struct BiomeLayer {
+    config: BiomeConfig,
+    surface_radius: f32,
+    sea_level: Option<f32>,
+    moisture: Noise,
+}
+
+impl BiomeLayer {
+    pub fn new(config: BiomeConfig, surface_radius: f32, sea_level: Option<f32>) -> Self {
+        Self {
+            config,
+            surface_radius,
+            sea_level,
+            moisture: Noise::new(config.seed),
+        }
+    }
+
+    pub fn config(&self) -> BiomeConfig {
+        self.config
+    }
+
+    /// The average temperature of the body, from how much light it receives from its star
+    pub fn average_temperature(&self) -> f32 {
+        HABITABLE_TEMPERATURE * (HABITABLE_DISTANCE / self.config.star_distance.max(1.0)).sqrt()
+    }
+
+    /// The temperature (in kelvin) in a direction from the centre of the body, at an
+    /// altitude relative to its radius
+    pub fn temperature(&self, direction: Vector3<f32>, altitude: f32) -> f32 {
+        // The sine of the latitude, the poles are on the y axis
+        let latitude = direction.y;
+
+        self.average_temperature() + EQUATOR_WARMING
+            - POLAR_COOLING * latitude * latitude
+            - LAPSE_RATE * altitude.max(0.0)
+    }
+
+    /// How wet the surface is in a direction, from 0 to 1
+    pub fn moisture(&self, direction: Vector3<f32>) -> f32 {
+        let noise = self
+            .moisture
+            .sphere(direction, MOISTURE_FREQUENCY, MOISTURE_OCTAVES);
+        ((noise + 1.0) / 2.0).clamp(0.0, 1.0)
+    }
+
+    /// The biome of the surface in a direction from the centre of the body, where the
+    /// surface is `height` from the centre
+    pub fn classify(&self, direction: Vector3<f32>, height: f32) -> Biome {
+        if let Some(sea_level) = self.sea_level {
+            if height < sea_level {
+                return Biome::Ocean;
+            }
+        }
+
+        let base = self.sea_level.unwrap_or(self.surface_radius);
+        let altitude = (height - base) / self.surface_radius;
+        let temperature = self.temperature(direction, altitude);
+        let moisture = self.moisture(direction);
+
+        if temperature < ICE_TEMPERATURE {
+            Biome::Ice
+        } else if altitude > MOUNTAIN_ALTITUDE {
+            Biome::Mountain
+        } else if temperature < TUNDRA_TEMPERATURE {
+            Biome::Tundra
+        } else if self.sea_level.is_some() && altitude < BEACH_ALTITUDE {
+            Biome::Beach
+        } else if moisture < DESERT_MOISTURE && temperature > DESERT_TEMPERATURE {
+            Biome::Desert
+        } else if moisture > FOREST_MOISTURE {
+            Biome::Forest
+        } else {
+            Biome::Grassland
+        }
+    }
+
+    /// Draw an equirectangular map of the biomes as RGBA pixels. `surface_height` is the
+    /// height of the surface in a direction.
+    pub fn map<F: Fn(Vector3<f32>) -> f32>(
+        &self,
+        width: u32,
+        height: u32,
+        surface_height: F,
+    ) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let uv = Vector2::new(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                let direction = sphere::equirectangular_direction(uv);
+                let color = self.classify(direction, surface_height(direction)).color();
+
+                pixels.extend_from_slice(&[
+                    (color.x * 255.0) as u8,
+                    (color.y * 255.0) as u8,
+                    (color.z * 255.0) as u8,
+                    255,
+                ]);
+            }
+        }
+
+        pixels
+    }
+}
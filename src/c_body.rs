@@ -1,48 +1,293 @@
+use crate::atmosphere::{Atmosphere, AtmosphereConfig};
+use crate::biome::{self, BiomeConfig, BiomeLayer};
+use crate::camera::Camera;
+use crate::chunks::ChunkSet;
+use crate::generator::{self, BodyGenerator, GeneratorParameters};
+use crate::lod::QuadTree;
+use crate::mass::{Mass, MassIntegral, MassProperties};
+use crate::material::{Material, MaterialConfig};
 use crate::mesh::Mesh;
-use crate::texture::Texture;
+use crate::normals::{self, NormalOptions};
+use crate::rotation::{self, RotationState};
+use crate::sphere;
+use crate::terrain::{self, TerrainEdit};
 use crate::uniform_buffer::{ModelUniform, UniformBuffer};
-use crate::utils::{Vertex, G};
-use cgmath::num_traits::FloatConst;
-use cgmath::{Quaternion, Vector2, Vector3};
+use crate::utils::{Vertex, G, UPDATES_PER_SECOND};
+use crate::worker::WorkerPool;
+use crate::{dual_contouring, marching_cubes, surface_nets};
+use cgmath::{InnerSpace, Matrix3, Quaternion, SquareMatrix, Vector2, Vector3, Zero};
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
 use std::time::Duration;
 
+/// The size (in voxels) of the chunks that a body's mesh is split into. Edits
+/// only re-mesh the chunks they touch.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Empty space
+pub const MATERIAL_AIR: u8 = 0;
+pub const MATERIAL_ROCK: u8 = 1;
+/// Dark, carbon rich rock found on asteroids
+pub const MATERIAL_DARK_ROCK: u8 = 2;
+/// Liquid below the sea level of a body. This is not solid, so it is not part of the
+/// terrain mesh.
+pub const MATERIAL_WATER: u8 = 3;
+/// Surface materials of biomes
+pub const MATERIAL_ICE: u8 = 4;
+pub const MATERIAL_SAND: u8 = 5;
+pub const MATERIAL_GRASS: u8 = 6;
+pub const MATERIAL_FOREST: u8 = 7;
+pub const MATERIAL_TUNDRA: u8 = 8;
+
+/// How many times the sphere drawn for an ocean is subdivided
+const OCEAN_SUBDIVISIONS: u32 = 5;
+
+/// The distance between samples when casting a ray against a body
+const RAYCAST_STEP: f32 = 0.25;
+
+/// The distance between samples when finding the slope of the density field
+const GRADIENT_STEP: f32 = 0.5;
+
+/// How many times the placeholder drawn while a body is generating is subdivided
+const PLACEHOLDER_SUBDIVISIONS: u32 = 2;
+
+/// How many times the cube is subdivided when exporting a body with a quadtree
+const EXPORT_SUBDIVISIONS: u32 = 64;
+
+/// The size of the cells that terrain edits are indexed by, so only the edits near a
+/// position are applied when sampling the density there
+const EDIT_CELL_SIZE: i32 = 8;
+
+/// The type of mesh that is generated for a body
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MeshType {
+    /// Voxels from the body generator, this allows the terrain to be edited
+    Voxel,
+    /// A subdivided icosahedron
+    Icosphere { subdivisions: u32 },
+    /// A subdivided cube, projected onto a sphere
+    CubeSphere { subdivisions: u32 },
+    /// A cube-sphere whose patches are refined around the camera
+    Quadtree { max_depth: u32 },
+}
+
+/// The algorithm used to build the meshes of voxel bodies
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mesher {
+    /// A cube for every solid voxel
+    Cubes,
+    MarchingCubes,
+    SurfaceNets,
+    DualContouring,
+}
+
+impl Mesher {
+    pub const ALL: [Mesher; 4] = [
+        Mesher::Cubes,
+        Mesher::MarchingCubes,
+        Mesher::SurfaceNets,
+        Mesher::DualContouring,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mesher::Cubes => "Cubes",
+            Mesher::MarchingCubes => "Marching Cubes",
+            Mesher::SurfaceNets => "Surface Nets",
+            Mesher::DualContouring => "Dual Contouring",
+        }
+    }
+}
+
+/// How the meshes of voxel bodies are built
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshOptions {
+    pub mesher: Mesher,
+    pub normals: NormalOptions,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            mesher: Mesher::Cubes,
+            normals: NormalOptions::default(),
+        }
+    }
+}
+
+/// Makes a body a star, it glows and lights the bodies around it
+#[derive(Copy, Clone, Debug)]
+pub struct StarConfig {
+    /// The colour of the light given off by the star
+    pub color: Vector3<f32>,
+    /// How much light the star gives off. The light falls off with the square of the
+    /// distance from the star.
+    pub luminosity: f32,
+    /// How bright the surface of the star is drawn. Brighter than 1 makes it glow.
+    pub surface_brightness: f32,
+}
+
+/// Configuration used to create a body
+#[derive(Clone, Debug)]
+pub struct CBodyConfig {
+    pub name: String,
+    pub mass: Mass,
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    /// The angular velocity (radians per update), in the local space of the body
+    pub angular_velocity: Vector3<f32>,
+    pub mesh_type: MeshType,
+    /// Stars glow and light the other bodies
+    pub star: Option<StarConfig>,
+    /// The distance from the centre of the body to the surface of its ocean
+    pub sea_level: Option<f32>,
+    /// Air that scatters the light of the stars around the body
+    pub atmosphere: Option<AtmosphereConfig>,
+    /// Covers the surface of the body with biomes
+    pub biomes: Option<BiomeConfig>,
+    /// How the surface reflects light
+    pub material: MaterialConfig,
+}
+
+impl Default for CBodyConfig {
+    fn default() -> Self {
+        Self {
+            name: "Body".to_string(),
+            mass: Mass::Fixed(1.0),
+            position: Vector3::new(0.0, 0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            angular_velocity: Vector3::new(0.0, 0.0, 0.0),
+            mesh_type: MeshType::Voxel,
+            star: None,
+            sea_level: None,
+            atmosphere: None,
+            biomes: None,
+            material: MaterialConfig::default(),
+        }
+    }
+}
+
 pub struct CBody {
     pub name: String,
     pub mass: f32,
+    /// The density of rock within the body, the mass is found from this whenever the
+    /// terrain changes
+    pub density: f32,
+    /// The centre of mass, in the local space of the body
+    pub center_of_mass: Vector3<f32>,
+    /// The inertia tensor about the centre of mass
+    pub inertia: Matrix3<f32>,
+    /// The distance from the centre of the body to its surface, set by its generator
     pub radius: f32,
     pub velocity: Vector3<f32>,
     pub position: Vector3<f32>,
+    /// The orientation of the body, it turns about the centre of mass
     pub rotation: Quaternion<f32>,
-    pub mesh: Mesh,
+    /// The angular velocity (radians per update), in the local space of the body
+    pub angular_velocity: Vector3<f32>,
+    pub star: Option<StarConfig>,
+    pub mesh_type: MeshType,
+    /// How voxel meshes are built
+    pub mesh_options: MeshOptions,
+    pub mesh: Option<Mesh>,
+    pub chunks: ChunkSet,
+    pub lod: Option<QuadTree>,
+    /// A simple sphere that is drawn until the real meshes have been generated
+    pub placeholder: Option<Mesh>,
+    /// A translucent sphere at the sea level of the body, drawn after all solid meshes
+    pub ocean: Option<Mesh>,
+    /// Drawn over everything else, as it is see-through
+    pub atmosphere: Option<Atmosphere>,
     pub uniform_buffer: UniformBuffer<ModelUniform>,
-    pub texture: Texture,
-    pub gen: CBodyGenerator,
+    pub material: Material,
+    pub gen: Arc<CBodyGenerator>,
+    /// The mass the terrain is being integrated for on a worker thread, and where the
+    /// result is sent. The mass properties are estimated until it arrives.
+    pending_mass: Option<(Mass, Receiver<MassProperties>)>,
 }
 
 impl CBody {
     pub fn new(
-        name: String,
-        mass: f32,
-        radius: f32,
-        position: Vector3<f32>,
-        velocity: Vector3<f32>,
-        texture: Texture,
+        config: CBodyConfig,
+        generator: Box<dyn BodyGenerator>,
+        material: Material,
+        pool: &WorkerPool,
         device: &wgpu::Device,
     ) -> Self {
-        let gen = CBodyGenerator::new(radius);
+        let gen = Arc::new(CBodyGenerator::new(generator, &config));
+
+        // Integrating the terrain takes a while, so the body starts out as a sphere
+        let MassProperties {
+            mass,
+            density,
+            center_of_mass,
+            inertia,
+        } = MassProperties::estimate(config.mass, &gen);
+        let pending_mass = Some(Self::request_mass_properties(config.mass, &gen, pool));
+
+        let CBodyConfig {
+            name,
+            position,
+            velocity,
+            rotation,
+            angular_velocity,
+            mesh_type,
+            star,
+            sea_level,
+            atmosphere,
+            ..
+        } = config;
+        let radius = gen.surface_radius();
 
         // Create the mesh for this body
-        let mesh = Self::build_mesh(radius, &gen, device);
-        //let mesh = Self::build_mesh_old(radius, 38, 16, device);
-        let rotation: Quaternion<f32> = Quaternion::new(0.0, 0.0, 0.0, 0.0);
+        let mesh_options = MeshOptions::default();
+        let mut mesh = None;
+        let mut chunks = ChunkSet::new();
+        let mut lod = None;
+        let mut placeholder = None;
+        match mesh_type {
+            MeshType::Voxel => {
+                // The chunks are built in the background
+                let (min, max) = gen.chunk_range();
+                chunks.request(&gen, min, max, mesh_options, pool);
+            }
+            MeshType::Icosphere { .. } | MeshType::CubeSphere { .. } => {
+                let (vertices, indices) = Self::build_geometry(&gen, mesh_type, mesh_options);
+                mesh = Some(Mesh::new(vertices, indices, device));
+            }
+            MeshType::Quadtree { max_depth } => {
+                // The patches are built the first time the body is updated
+                lod = Some(QuadTree::new(max_depth));
+            }
+        }
+
+        if mesh.is_none() {
+            let (vertices, indices) =
+                sphere::build_icosphere(gen.surface_radius(), PLACEHOLDER_SUBDIVISIONS);
+            placeholder = Some(Mesh::new(vertices, indices, device));
+        }
+
+        let ocean = sea_level.map(|sea_level| {
+            let (vertices, indices) = sphere::build_icosphere(sea_level, OCEAN_SUBDIVISIONS);
+            Mesh::new(vertices, indices, device)
+        });
+
+        let atmosphere =
+            atmosphere.map(|config| Atmosphere::new(config, gen.surface_radius(), device));
 
         let uniform_data = ModelUniform {
             model: cgmath::Matrix4::from_translation(position) * cgmath::Matrix4::from(rotation),
+            // Stars light themselves
+            emission_color: star.map_or_else(Vector3::zero, |s| s.color),
+            emission: star.map_or(0.0, |s| s.surface_brightness),
         };
 
         let uniform_buffer = UniformBuffer::new(
             "C-Body Uniform Buffer",
-            wgpu::ShaderStage::VERTEX,
+            wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
             uniform_data,
             device,
         );
@@ -50,14 +295,56 @@ impl CBody {
         Self {
             name,
             mass,
+            density,
+            center_of_mass,
+            inertia,
             radius,
             velocity,
             position,
             rotation,
+            angular_velocity,
+            star,
+            mesh_type,
+            mesh_options,
             mesh,
+            chunks,
+            lod,
+            placeholder,
+            ocean,
+            atmosphere,
             uniform_buffer,
-            texture,
+            material,
             gen,
+            pending_mass,
+        }
+    }
+
+    /// Integrate the mass of the terrain on a worker thread
+    fn request_mass_properties(
+        mass: Mass,
+        gen: &Arc<CBodyGenerator>,
+        pool: &WorkerPool,
+    ) -> (Mass, Receiver<MassProperties>) {
+        let (sender, receiver) = channel();
+        let gen = Arc::clone(gen);
+        pool.execute(move || {
+            // The body may have been destroyed, or edited, while this was running
+            let _ = sender.send(MassProperties::new(mass, &gen));
+        });
+
+        (mass, receiver)
+    }
+
+    /// Use the integrated mass properties once they have been found
+    fn receive_mass_properties(&mut self) {
+        let received = match &self.pending_mass {
+            Some((_, receiver)) => receiver.try_recv().ok(),
+            None => None,
+        };
+
+        if let Some(properties) = received {
+            self.set_mass_properties(properties);
+            self.pending_mass = None;
         }
     }
 
@@ -77,15 +364,22 @@ impl CBody {
         nd.sqrt()
     }
 
-    pub fn update(&mut self, _dt: Duration) {
-        //let rotation_speed_deg: f32 = 0.01;
-        //let rotation_speed: f32 = rotation_speed_deg * f32::PI() / 180.0;
+    /// Move and turn the body. The torque is in the local space of the body.
+    pub fn update(&mut self, torque: Vector3<f32>, dt: Duration) {
+        // The body turns about its centre of mass, so its origin moves as it turns
+        let center_of_mass = self.center_of_mass_position();
+        let RotationState {
+            orientation,
+            angular_velocity,
+        } = RotationState {
+            orientation: self.rotation,
+            angular_velocity: self.angular_velocity,
+        }
+        .step(self.inertia, torque, dt.as_secs_f32() * UPDATES_PER_SECOND);
 
-        //let rot: Quaternion<f32> = Quaternion::from_axis_angle(
-        //    Vector3::new(1.0, 0.0, 0.0).normalize(),
-        //    Rad(rotation_speed),
-        //);
-        //self.rotation = self.rotation * rot;
+        self.rotation = orientation;
+        self.angular_velocity = angular_velocity;
+        self.position = center_of_mass - self.rotation * self.center_of_mass;
 
         //let force = self.mass * self.velocity
 
@@ -100,27 +394,376 @@ impl CBody {
             cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation);
     }
 
-    fn build_mesh(radius: f32, gen: &CBodyGenerator, device: &wgpu::Device) -> Mesh {
+    /// All of the meshes that need to be drawn for this body
+    pub fn meshes(&self) -> Vec<&Mesh> {
+        if let Some(placeholder) = &self.placeholder {
+            return vec![placeholder];
+        }
+
+        let mut meshes: Vec<&Mesh> = self.mesh.iter().chain(self.chunks.meshes()).collect();
+        if let Some(lod) = &self.lod {
+            lod.collect_meshes(&mut meshes);
+        }
+
+        meshes
+    }
+
+    /// Upload any meshes that have finished generating, and refine the level of detail
+    /// of this body around the camera
+    pub fn update_meshes(
+        &mut self,
+        camera: &Camera,
+        screen_height: u32,
+        pool: &WorkerPool,
+        device: &wgpu::Device,
+    ) {
+        self.receive_mass_properties();
+        self.chunks.receive(device);
+
+        let camera_position = self.to_local(camera.position);
+        let pixels_per_radian = camera.projection.pixels_per_radian(screen_height);
+
+        if let Some(lod) = &mut self.lod {
+            lod.update(camera_position, pixels_per_radian, &self.gen, pool, device);
+        }
+
+        // Once the body has been generated, the placeholder is no longer needed
+        let ready = self.chunks.is_ready() && self.lod.iter().all(|l| l.is_ready());
+        if ready {
+            self.placeholder = None;
+        }
+    }
+
+    /// The centre of mass of this body, in world space
+    pub fn center_of_mass_position(&self) -> Vector3<f32> {
+        self.position + self.rotation * self.center_of_mass
+    }
+
+    /// The torque (in local space) on this body from the gravity of another
+    pub fn gravity_gradient_torque(&self, other: &CBody) -> Vector3<f32> {
+        let offset = self.rotation.conjugate()
+            * (other.center_of_mass_position() - self.center_of_mass_position());
+        rotation::gravity_gradient_torque(offset, other.mass, self.inertia)
+    }
+
+    /// Whether every mesh of this body has been built, so it is drawn in full detail
+    pub fn is_complete(&self) -> bool {
+        self.chunks.is_ready() && self.lod.iter().all(|l| l.is_complete())
+    }
+
+    /// Convert a point in world space into the local space of this body
+    pub fn to_local(&self, point: Vector3<f32>) -> Vector3<f32> {
+        self.rotation.conjugate() * (point - self.position)
+    }
+
+    /// Cast a ray (in world space) against the terrain of this body, returning the
+    /// distance along the ray to the first solid voxel
+    pub fn raycast(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> Option<f32> {
+        let local_origin = self.to_local(origin);
+        let local_direction = self.rotation.conjugate() * direction;
+
+        // Only march the section of the ray that is within the bounds of the body
+        let bound = self.gen.extent() as f32 * 3.0_f32.sqrt();
+        let b = local_origin.dot(local_direction);
+        let c = local_origin.magnitude2() - bound * bound;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let end = -b + discriminant.sqrt();
+        let mut t = (-b - discriminant.sqrt()).max(0.0);
+
+        while t < end {
+            let position = local_origin + local_direction * t;
+            if is_solid(self.gen.get_material(position.map(f32::floor))) {
+                return Some(t);
+            }
+
+            t += RAYCAST_STEP;
+        }
+
+        None
+    }
+
+    /// Apply an edit (in local space) to the terrain of this body
+    pub fn apply_edit(&mut self, edit: TerrainEdit, pool: &WorkerPool) {
+        self.apply_edits(&[edit], pool);
+    }
+
+    /// Apply a set of edits (in local space) to the terrain of this body. Only the
+    /// chunks touched by the edits are re-meshed.
+    pub fn apply_edits(&mut self, edits: &[TerrainEdit], pool: &WorkerPool) {
+        let (range_min, range_max) = self.gen.chunk_range();
+
+        // Removing or adding terrain changes the mass of the body, only the mass around
+        // each edit needs to be integrated again. Edits also change which material is
+        // near the surface, a little past their bounds.
+        let mut properties = self.mass_properties();
+        let margin = biome::BIOME_DEPTH + 1.0;
+        let margin = Vector3::new(margin, margin, margin);
+
+        // Any patches that are still building keep their own copy of the generator
+        let gen = Arc::make_mut(&mut self.gen);
+        for edit in edits {
+            let (min, max) = edit.bounds();
+            let region = (min - margin, max + margin);
+
+            let before = MassIntegral::within(gen, properties.density, region);
+            gen.add_edit(*edit);
+            let after = MassIntegral::within(gen, properties.density, region);
+            properties = properties.with_change(before, after);
+        }
+
+        self.set_mass_properties(properties);
+
+        // The terrain that is still being integrated is out of date
+        if let Some((mass, _)) = self.pending_mass {
+            self.pending_mass = Some(Self::request_mass_properties(mass, &self.gen, pool));
+        }
+
+        if let Some(lod) = &mut self.lod {
+            lod.invalidate();
+        }
+
+        // Only voxel meshes can show the edits
+        if self.mesh_type != MeshType::Voxel {
+            return;
+        }
+
+        for edit in edits {
+            let (min, max) = terrain::chunks_in_bounds(edit.bounds(), CHUNK_SIZE);
+
+            let min = Vector3::new(
+                min.x.max(range_min.x),
+                min.y.max(range_min.y),
+                min.z.max(range_min.z),
+            );
+            let max = Vector3::new(
+                max.x.min(range_max.x),
+                max.y.min(range_max.y),
+                max.z.min(range_max.z),
+            );
+
+            self.chunks
+                .request(&self.gen, min, max, self.mesh_options, pool);
+        }
+    }
+
+    /// Change how voxel meshes are built, rebuilding every chunk
+    pub fn set_mesh_options(&mut self, mesh_options: MeshOptions, pool: &WorkerPool) {
+        self.mesh_options = mesh_options;
+
+        if self.mesh_type == MeshType::Voxel {
+            let (min, max) = self.gen.chunk_range();
+            self.chunks.request(&self.gen, min, max, mesh_options, pool);
+        }
+    }
+
+    /// The configuration that would create this body as it is now
+    pub fn config(&self) -> CBodyConfig {
+        CBodyConfig {
+            name: self.name.clone(),
+            mass: Mass::Fixed(self.mass),
+            position: self.position,
+            velocity: self.velocity,
+            rotation: self.rotation,
+            angular_velocity: self.angular_velocity,
+            mesh_type: self.mesh_type,
+            star: self.star,
+            sea_level: self.gen.sea_level(),
+            atmosphere: self.atmosphere.as_ref().map(|a| a.config),
+            biomes: self.gen.biome_config(),
+            material: self.material.config,
+        }
+    }
+
+    /// How many triangles are drawn for this body
+    pub fn triangle_count(&self) -> u32 {
+        self.meshes().iter().map(|m| m.triangle_count()).sum()
+    }
+
+    /// The smaller body has struck this body. Carve a crater where it hit, sized by the
+    /// energy of the impact, and absorb its mass and momentum.
+    pub fn absorb_impact(&mut self, impactor: &CBody, pool: &WorkerPool) {
+        let impact_velocity = (impactor.velocity - self.velocity).magnitude();
+        let normal = (self.to_local(impactor.position)).normalize();
+
+        let edits = terrain::crater_from_impact(
+            normal * self.gen.surface_radius(),
+            normal,
+            impactor.mass,
+            impact_velocity,
+            impactor.gen.surface_radius(),
+            self.gen.surface_radius() * 0.5,
+        );
+
+        // Conserve momentum
+        let mass = self.mass + impactor.mass;
+        let relative_velocity = impactor.velocity - self.velocity;
+        self.velocity = (self.velocity * self.mass + impactor.velocity * impactor.mass) / mass;
+
+        // Conserve angular momentum about the centre of mass, an impact that is off
+        // centre spins the body up
+        let offset = self.to_local(impactor.position) - self.center_of_mass;
+        let angular_momentum = self.inertia * self.angular_velocity
+            + offset.cross(self.rotation.conjugate() * relative_velocity) * impactor.mass;
+
+        self.apply_edits(&edits, pool);
+
+        // The material of the impactor is spread through the body, so it is denser
+        // than the crater alone would leave it
+        self.set_mass_properties(self.mass_properties().with_mass(mass));
+        if self.pending_mass.is_some() {
+            self.pending_mass = Some(Self::request_mass_properties(
+                Mass::Fixed(mass),
+                &self.gen,
+                pool,
+            ));
+        }
+
+        if let Some(inverse_inertia) = self.inertia.invert() {
+            self.angular_velocity = inverse_inertia * angular_momentum;
+        }
+    }
+
+    /// The mass of this body and how it is spread through its volume
+    pub fn mass_properties(&self) -> MassProperties {
+        MassProperties {
+            mass: self.mass,
+            density: self.density,
+            center_of_mass: self.center_of_mass,
+            inertia: self.inertia,
+        }
+    }
+
+    fn set_mass_properties(&mut self, properties: MassProperties) {
+        self.mass = properties.mass;
+        self.density = properties.density;
+        self.center_of_mass = properties.center_of_mass;
+        self.inertia = properties.inertia;
+    }
+
+    /// Build the complete mesh of a body on the CPU, this is used when exporting
+    pub fn build_geometry(
+        gen: &CBodyGenerator,
+        mesh_type: MeshType,
+        mesh_options: MeshOptions,
+    ) -> (Vec<Vertex>, Vec<u32>) {
+        match mesh_type {
+            MeshType::Voxel => {
+                let mut vertices: Vec<Vertex> = Vec::new();
+                let mut indices: Vec<u32> = Vec::new();
+
+                let (min, max) = gen.chunk_range();
+                for cx in min.x..=max.x {
+                    for cy in min.y..=max.y {
+                        for cz in min.z..=max.z {
+                            let (chunk_vertices, chunk_indices) =
+                                Self::build_mesh(gen, Vector3::new(cx, cy, cz), mesh_options);
+
+                            let offset = vertices.len() as u32;
+                            vertices.extend(chunk_vertices);
+                            indices.extend(chunk_indices.iter().map(|i| i + offset));
+                        }
+                    }
+                }
+
+                (vertices, indices)
+            }
+            MeshType::Icosphere { subdivisions } => {
+                let (mut vertices, indices) = sphere::build_icosphere(1.0, subdivisions);
+                Self::displace_sphere(gen, &mut vertices);
+                (vertices, indices)
+            }
+            MeshType::CubeSphere { subdivisions } => {
+                let (mut vertices, indices) = sphere::build_cube_sphere(1.0, subdivisions);
+                Self::displace_sphere(gen, &mut vertices);
+                (vertices, indices)
+            }
+            MeshType::Quadtree { .. } => {
+                // Export at a fixed level of detail, following the surface of the terrain
+                let (mut vertices, indices) = sphere::build_cube_sphere(1.0, EXPORT_SUBDIVISIONS);
+                for vertex in vertices.iter_mut() {
+                    vertex.position *= gen.get_surface_height(vertex.normal);
+                }
+
+                (vertices, indices)
+            }
+        }
+    }
+
+    /// Move the vertices of a unit sphere out to the surface of the generated terrain. Normals
+    /// are found from the slope of the terrain around each vertex.
+    fn displace_sphere(gen: &CBodyGenerator, vertices: &mut [Vertex]) {
+        let epsilon = gen.detail_angle();
+        let surface = |direction: Vector3<f32>| direction * gen.base_height(direction);
+
+        for vertex in vertices.iter_mut() {
+            let direction = vertex.position.normalize();
+            let position = surface(direction);
+
+            let bitangent = direction.cross(vertex.tangent);
+            let along_tangent = surface((direction + vertex.tangent * epsilon).normalize());
+            let along_bitangent = surface((direction + bitangent * epsilon).normalize());
+
+            let normal = (along_tangent - position)
+                .cross(along_bitangent - position)
+                .normalize();
+
+            vertex.position = position;
+            vertex.normal = if normal.dot(direction) < 0.0 {
+                -normal
+            } else {
+                normal
+            };
+        }
+    }
+
+    /// Build the vertices and indices for a chunk of voxels. This only samples the
+    /// generator, so it can be run on a worker thread.
+    pub fn build_mesh(
+        gen: &CBodyGenerator,
+        chunk: Vector3<i32>,
+        mesh_options: MeshOptions,
+    ) -> (Vec<Vertex>, Vec<u32>) {
+        let (mut vertices, mut indices) = match mesh_options.mesher {
+            Mesher::Cubes => Self::build_cubes(gen, chunk),
+            Mesher::MarchingCubes => marching_cubes::build_mesh(gen, chunk),
+            Mesher::SurfaceNets => surface_nets::build_mesh(gen, chunk),
+            Mesher::DualContouring => dual_contouring::build_mesh(gen, chunk),
+        };
+
+        normals::apply(mesh_options.normals, gen, &mut vertices, &mut indices);
+
+        (vertices, indices)
+    }
+
+    /// Build a cube for every solid voxel in a chunk, skipping faces that are hidden
+    /// by a neighbouring voxel
+    fn build_cubes(gen: &CBodyGenerator, chunk: Vector3<i32>) -> (Vec<Vertex>, Vec<u32>) {
         // Build the vertices for the mesh
         let mut vertices: Vec<Vertex> = Vec::new();
         let mut indices: Vec<u32> = Vec::new();
 
         let mut curr_index: u32 = 0;
 
-        let half_radius: i32 = radius as i32 / 2;
+        let origin = chunk * CHUNK_SIZE;
 
-        for xi in -half_radius..half_radius {
-            for yi in -half_radius..half_radius as i32 {
-                for zi in -half_radius..half_radius as i32 {
+        for xi in origin.x..origin.x + CHUNK_SIZE {
+            for yi in origin.y..origin.y + CHUNK_SIZE {
+                for zi in origin.z..origin.z + CHUNK_SIZE {
                     let x = xi as f32;
                     let y = yi as f32;
                     let z = zi as f32;
 
                     let mat = gen.get_material(Vector3::new(x, y, z));
-                    if mat == 0 {
+                    if !is_solid(mat) {
                         continue;
                     }
 
+                    let first_vertex = vertices.len();
+
                     // FRONT
                     if gen.is_transparent(x, y, z - 1.0) {
                         vertices.push(Vertex::with_tex_coords(
@@ -324,148 +967,256 @@ impl CBody {
 
                         curr_index = curr_index + 4;
                     }
+
+                    // Colour all the faces of this voxel by its material
+                    let color = material_color(mat);
+                    for vertex in vertices[first_vertex..].iter_mut() {
+                        vertex.color = color;
+                    }
                 }
             }
         }
 
-        // Create the mesh for this body
-        Mesh::new(vertices, indices, device)
+        (vertices, indices)
     }
+}
 
-    fn _build_mesh_old(
-        radius: f32,
-        sector_count: u32,
-        stack_count: u32,
-        device: &wgpu::Device,
-    ) -> Mesh {
-        // Build the vertices for the mesh
-        let mut vertices: Vec<Vertex> = Vec::new();
-        let mut indices: Vec<u32> = Vec::new();
+/// Whether a material is part of the solid terrain
+pub fn is_solid(material: u8) -> bool {
+    material != MATERIAL_AIR && material != MATERIAL_WATER
+}
 
-        // vertex position
-        let mut x: f32;
-        let mut y: f32;
-        let mut z: f32;
-        let mut xy: f32;
+/// How dense a material is compared to rock
+pub fn material_density(material: u8) -> f32 {
+    match material {
+        MATERIAL_AIR => 0.0,
+        MATERIAL_ROCK => 1.0,
+        MATERIAL_DARK_ROCK => 0.7,
+        MATERIAL_WATER => 0.37,
+        MATERIAL_ICE => 0.34,
+        MATERIAL_SAND => 0.6,
+        MATERIAL_GRASS | MATERIAL_FOREST | MATERIAL_TUNDRA => 0.5,
+        _ => 1.0,
+    }
+}
 
-        // vertex normal
-        let mut nx: f32;
-        let mut ny: f32;
-        let mut nz: f32;
-        let length_inv: f32 = 1.0 / radius;
+/// The colour used to represent a material, such as when exporting meshes
+pub fn material_color(material: u8) -> Vector3<f32> {
+    match material {
+        MATERIAL_ROCK => Vector3::new(0.5, 0.45, 0.4),
+        MATERIAL_DARK_ROCK => Vector3::new(0.3, 0.28, 0.26),
+        MATERIAL_WATER => Vector3::new(0.05, 0.2, 0.4),
+        MATERIAL_ICE => Vector3::new(0.9, 0.95, 1.0),
+        MATERIAL_SAND => Vector3::new(0.85, 0.75, 0.5),
+        MATERIAL_GRASS => Vector3::new(0.35, 0.6, 0.25),
+        MATERIAL_FOREST => Vector3::new(0.15, 0.4, 0.15),
+        MATERIAL_TUNDRA => Vector3::new(0.55, 0.55, 0.45),
+        _ => Vector3::new(1.0, 0.0, 1.0),
+    }
+}
 
-        // vertex texCoord
-        let mut s: f32;
-        let mut t: f32;
+/// The terrain of a body, the generator it was created from along with any edits that
+/// have been made to it since
+#[derive(Clone)]
+pub struct CBodyGenerator {
+    base: Arc<dyn BodyGenerator>,
+    sea_level: Option<f32>,
+    biomes: Option<BiomeLayer>,
+    edits: Vec<TerrainEdit>,
+    /// The edits that can change each cell, as indices into `edits` in the order they
+    /// were made
+    edit_cells: HashMap<Vector3<i32>, Vec<usize>>,
+}
+impl CBodyGenerator {
+    /// Wrap a generator, with the sea level and biomes of a body
+    pub fn new(base: Box<dyn BodyGenerator>, config: &CBodyConfig) -> Self {
+        let biomes = config
+            .biomes
+            .map(|b| BiomeLayer::new(b, base.surface_radius(), config.sea_level));
 
-        let sector_step: f32 = 2.0 * f32::PI() / sector_count as f32;
-        let stack_step: f32 = f32::PI() / stack_count as f32;
-        let mut sector_angle: f32;
-        let mut stack_angle: f32;
+        Self {
+            base: Arc::from(base),
+            sea_level: config.sea_level,
+            biomes,
+            edits: Vec::new(),
+            edit_cells: HashMap::new(),
+        }
+    }
 
-        for i in 0..stack_count + 1 {
-            stack_angle = f32::PI() / 2.0 - i as f32 * stack_step; // starting from pi/2 to -pi/2
-            xy = radius * stack_angle.cos(); // r * cos(u)
-            z = radius * stack_angle.sin(); // r * sin(u)
+    pub fn has_biomes(&self) -> bool {
+        self.biomes.is_some()
+    }
 
-            // add (sectorCount+1) vertices per stack
-            // the first and last vertices have same position and normal, but different tex coords
-            for j in 0..sector_count + 1 {
-                sector_angle = j as f32 * sector_step; // starting from 0 to 2pi
+    pub fn biome_config(&self) -> Option<BiomeConfig> {
+        self.biomes.as_ref().map(|b| b.config())
+    }
 
-                // vertex position (x, y, z)
-                x = xy * sector_angle.cos(); // r * cos(u) * cos(v)
-                y = xy * sector_angle.sin(); // r * cos(u) * sin(v)
+    pub fn sea_level(&self) -> Option<f32> {
+        self.sea_level
+    }
 
-                let position = cgmath::Vector3::new(x, y, z);
+    /// The parameters of the generator that the terrain came from
+    pub fn parameters(&self) -> GeneratorParameters {
+        self.base.parameters()
+    }
 
-                // normalized vertex normal (nx, ny, nz)
-                nx = x * length_inv;
-                ny = y * length_inv;
-                nz = z * length_inv;
+    /// Draw an equirectangular map of the biomes on the surface as RGBA pixels
+    pub fn biome_map(&self, width: u32, height: u32) -> Option<Vec<u8>> {
+        self.biomes
+            .as_ref()
+            .map(|b| b.map(width, height, |d| self.get_surface_height(d)))
+    }
 
-                let normal = cgmath::Vector3::new(nx, ny, nz);
+    /// The height of the terrain in a direction from the centre of the body,
+    /// ignoring any edits
+    pub fn base_height(&self, direction: Vector3<f32>) -> f32 {
+        self.base.surface_height(direction)
+    }
 
-                // vertex tex coord (s, t) range between [0, 1]
-                s = (j / sector_count) as f32;
-                t = (i / stack_count) as f32;
+    /// The smallest angle between details on the surface of the body
+    pub fn detail_angle(&self) -> f32 {
+        self.base.detail_angle()
+    }
 
-                let tex_coord = cgmath::Vector2::new(s, t);
+    /// The radius of the generated surface
+    pub fn surface_radius(&self) -> f32 {
+        self.base.surface_radius()
+    }
 
-                vertices.push(Vertex::with_tex_coords(position, normal, tex_coord));
-            }
-        }
+    /// The distance from the centre of the body to the furthest generated terrain
+    pub fn bounds(&self) -> f32 {
+        self.base.bounds()
+    }
 
-        let mut k1: u32;
-        let mut k2: u32;
+    /// Half the size of the cube that terrain can exist in. This leaves some
+    /// room above the surface for terrain to be added.
+    pub fn extent(&self) -> i32 {
+        let bounds = self.bounds();
+        (bounds + (bounds * 0.25).max(2.0)).ceil() as i32
+    }
 
-        for i in 0u32..stack_count {
-            k1 = i * (sector_count + 1); // beginning of current stack
-            k2 = k1 + sector_count + 1; // beginning of next stack
+    /// The (inclusive) range of chunk coordinates that covers the terrain
+    pub fn chunk_range(&self) -> (Vector3<i32>, Vector3<i32>) {
+        let chunks = (self.extent() + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        (
+            Vector3::new(-chunks, -chunks, -chunks),
+            Vector3::new(chunks - 1, chunks - 1, chunks - 1),
+        )
+    }
 
-            for _j in 0u32..sector_count {
-                // 2 triangles per sector excluding first and last stacks
-                // k1 => k2 => k1+1
-                if i != 0 {
-                    indices.push(k1);
-                    indices.push(k2);
-                    indices.push(k1 + 1);
+    /// Add an edit to the density field
+    pub fn add_edit(&mut self, edit: TerrainEdit) {
+        let index = self.edits.len();
+        self.edits.push(edit);
+
+        // Past its bounds an edit only changes how deep the terrain is, which decides
+        // where the biomes are near the surface
+        let margin = Vector3::new(biome::BIOME_DEPTH, biome::BIOME_DEPTH, biome::BIOME_DEPTH);
+        let (min, max) = edit.bounds();
+        let (min, max) = (edit_cell(min - margin), edit_cell(max + margin));
+
+        // Nothing is sampled outside of the extent
+        let limit = self.extent() / EDIT_CELL_SIZE + 1;
+        for x in min.x.max(-limit)..=max.x.min(limit) {
+            for y in min.y.max(-limit)..=max.y.min(limit) {
+                for z in min.z.max(-limit)..=max.z.min(limit) {
+                    self.edit_cells
+                        .entry(Vector3::new(x, y, z))
+                        .or_default()
+                        .push(index);
                 }
+            }
+        }
+    }
 
-                // k1+1 => k2 => k2+1
-                if i != (stack_count - 1) {
-                    indices.push(k1 + 1);
-                    indices.push(k2);
-                    indices.push(k2 + 1);
-                }
+    pub fn is_transparent(&self, x: f32, y: f32, z: f32) -> bool {
+        !is_solid(self.get_material(Vector3::new(x, y, z)))
+    }
 
-                k1 += 1;
-                k2 += 1;
+    /// The density of the terrain at a position, positive values are solid
+    pub fn get_density(&self, position: Vector3<f32>) -> f32 {
+        // Nothing can exist outside of the bounds
+        let extent = self.extent() as f32;
+        if position.x.abs() > extent || position.y.abs() > extent || position.z.abs() > extent {
+            return -1.0;
+        }
+
+        let mut density = self.base.density(position);
+        if let Some(edits) = self.edit_cells.get(&edit_cell(position)) {
+            for &index in edits {
+                density = self.edits[index].apply(position, density);
             }
         }
 
-        // Create the mesh for this body
-        Mesh::new(vertices, indices, device)
+        density
     }
-}
 
-pub struct CBodyGenerator {
-    radius: f32,
-}
-impl CBodyGenerator {
-    pub fn new(radius: f32) -> Self {
-        Self { radius }
+    /// The gradient of the density field at a position, using central differences
+    pub fn get_gradient(&self, position: Vector3<f32>) -> Vector3<f32> {
+        let h = GRADIENT_STEP;
+        let difference = |offset: Vector3<f32>| {
+            (self.get_density(position + offset) - self.get_density(position - offset)) / (2.0 * h)
+        };
+
+        Vector3::new(
+            difference(Vector3::new(h, 0.0, 0.0)),
+            difference(Vector3::new(0.0, h, 0.0)),
+            difference(Vector3::new(0.0, 0.0, h)),
+        )
     }
 
-    pub fn is_transparent(&self, x: f32, y: f32, z: f32) -> bool {
-        // Always show on the outside
-        //if x <= 0.0 || x >= self.radius || y <= 0.0 || y >= self.radius || z <= 0.0 || z >= self.radius {
-        //    return false;
-        //}
+    /// The direction out of the terrain at a position. The density decreases towards
+    /// the outside, so this is against the gradient.
+    pub fn get_normal(&self, position: Vector3<f32>) -> Vector3<f32> {
+        let gradient = self.get_gradient(position);
+        if gradient.magnitude2() > 0.0 {
+            -gradient.normalize()
+        } else if position.magnitude2() > 0.0 {
+            position.normalize()
+        } else {
+            Vector3::unit_y()
+        }
+    }
 
-        if self.get_material(Vector3::new(x, y, z)) == 0 {
-            return true;
+    /// Find the height of the surface along a direction from the centre of the body
+    pub fn get_surface_height(&self, direction: Vector3<f32>) -> f32 {
+        if self.edits.is_empty() {
+            return self.base.surface_height(direction);
         }
 
-        return true;
+        generator::find_surface(
+            |p| self.get_density(p),
+            direction,
+            self.extent() as f32 * 3.0_f32.sqrt(),
+        )
     }
 
     pub fn get_material(&self, position: Vector3<f32>) -> u8 {
-        let half_radius: f32 = self.radius as f32 / 2.0;
+        let density = self.get_density(position);
+        if density >= 0.0 {
+            // The top of the terrain is covered by the biome it's in
+            let distance = position.magnitude();
+            if let Some(biomes) = &self.biomes {
+                if density < biome::BIOME_DEPTH && distance > 0.0 {
+                    return biomes.classify(position / distance, distance).material();
+                }
+            }
 
-        let x = half_radius + position.x;
-        let y = half_radius + position.y;
-        let z = half_radius + position.z;
+            return self.base.material(position);
+        }
 
-        if ((x - self.radius / 2.0) * (x - self.radius / 2.0)
-            + (y - self.radius / 2.0) * (y - self.radius / 2.0)
-            + (z - self.radius / 2.0) * (z - self.radius / 2.0))
-            .sqrt()
-            <= self.radius / 2.0
-        {
-            return 1;
+        // Empty space below the sea level is flooded
+        if let Some(sea_level) = self.sea_level {
+            if position.magnitude() < sea_level {
+                return MATERIAL_WATER;
+            }
         }
 
-        return 0;
+        MATERIAL_AIR
     }
 }
+
+/// The cell that edits are indexed by which contains a position
+fn edit_cell(position: Vector3<f32>) -> Vector3<i32> {
+    (position / EDIT_CELL_SIZE as f32).map(|c| c.floor() as i32)
+}
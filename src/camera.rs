@@ -1,7 +1,9 @@
 use crate::uniform_buffer::{CameraUniform, UniformBuffer};
 use crate::utils::OPENGL_TO_WGPU_MATRIX;
 use cgmath::num_traits::FloatConst;
-use cgmath::{Angle, EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3};
+use cgmath::{
+    Angle, EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3, Vector4,
+};
 use std::f32::consts::FRAC_PI_2;
 use std::time::Duration;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
@@ -31,6 +33,11 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    /// How many pixels an angle of one radian covers in the centre of the screen
+    pub fn pixels_per_radian(&self, height: u32) -> f32 {
+        height as f32 / (2.0 * (self.fov_y / 2.0).tan())
+    }
+
     /// Calculate the projection matrix for the window
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX
@@ -62,6 +69,7 @@ impl Camera {
             wgpu::ShaderStage::VERTEX,
             CameraUniform {
                 view_proj: Matrix4::identity(),
+                view_position: position.extend(1.0),
             },
             &device,
         );
@@ -88,9 +96,53 @@ impl Camera {
         )
     }
 
+    /// Calculate the direction (in world space) of a ray leaving the camera through
+    /// the provided point on the screen
+    pub fn screen_ray(&self, x: f32, y: f32, width: f32, height: f32) -> Vector3<f32> {
+        let ndc_x = 2.0 * x / width - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / height;
+
+        let inverse = (self.projection.calc_matrix() * self.calc_matrix())
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+
+        // Un-project points on the near and far planes
+        let near = inverse * Vector4::new(ndc_x, ndc_y, 0.0, 1.0);
+        let far = inverse * Vector4::new(ndc_x, ndc_y, 1.0, 1.0);
+
+        (far.truncate() / far.w - near.truncate() / near.w).normalize()
+    }
+
+    /// Turn the camera to face a point
+    pub fn look_at(&mut self, target: Vector3<f32>) {
+        let direction = (target - self.position).normalize();
+        self.yaw = Rad(direction.z.atan2(direction.x));
+        self.pitch = Rad(direction.y.asin());
+
+        self.update_vectors();
+    }
+
+    /// Find the direction the camera is facing from its yaw and pitch
+    pub fn update_vectors(&mut self) {
+        // Calculate the new Front vector
+        self.front = Vector3::new(
+            self.yaw.cos() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.sin() * self.pitch.cos(),
+        )
+        .normalize();
+
+        // Also re-calculate the Right and Up vector
+        // Normalize the vectors, because their length gets closer
+        // to 0 the more you look up or down which results in slower movement.
+        self.right = self.front.cross(self.world_up).normalize();
+        self.up = self.right.cross(self.front).normalize();
+    }
+
     /// Update the uniforms for the camera, and write to the GPU
     pub fn update_uniforms(&mut self, queue: &wgpu::Queue) {
         self.uniform_buffer.data.view_proj = self.projection.calc_matrix() * self.calc_matrix();
+        self.uniform_buffer.data.view_position = self.position.extend(1.0);
 
         queue.write_buffer(
             &self.uniform_buffer.buffer,
@@ -230,19 +282,6 @@ impl CameraController {
         }
 
         // Update internals
-
-        // Calculate the new Front vector
-        camera.front = Vector3::new(
-            camera.yaw.cos() * camera.pitch.cos(),
-            camera.pitch.sin(),
-            camera.yaw.sin() * camera.pitch.cos(),
-        )
-        .normalize();
-
-        // Also re-calculate the Right and Up vector
-        // Normalize the vectors, because their length gets closer
-        // to 0 the more you look up or down which results in slower movement.
-        camera.right = camera.front.cross(camera.world_up).normalize();
-        camera.up = camera.right.cross(camera.front).normalize();
+        camera.update_vectors();
     }
 }
@@ -0,0 +1,116 @@
+use crate::c_body::{CBody, CBodyGenerator, MeshOptions};
+use crate::mesh::Mesh;
+use crate::utils::Vertex;
+use crate::worker::WorkerPool;
+use cgmath::Vector3;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+type ChunkKey = (i32, i32, i32);
+
+/// A finished chunk built on a worker thread
+struct ChunkResult {
+    key: ChunkKey,
+    version: u32,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+/// The voxel meshes of a body. Chunks are sampled and meshed on worker threads, and
+/// uploaded to the GPU once they are finished.
+pub struct ChunkSet {
+    meshes: HashMap<ChunkKey, Mesh>,
+    /// The latest version of each chunk that has been requested, older results are dropped
+    versions: HashMap<ChunkKey, u32>,
+    /// How many requested chunks have not been received yet
+    pending: usize,
+    sender: Sender<ChunkResult>,
+    receiver: Receiver<ChunkResult>,
+}
+
+impl ChunkSet {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+
+        Self {
+            meshes: HashMap::new(),
+            versions: HashMap::new(),
+            pending: 0,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Whether every requested chunk has been built
+    pub fn is_ready(&self) -> bool {
+        self.pending == 0
+    }
+
+    pub fn meshes(&self) -> impl Iterator<Item = &Mesh> {
+        self.meshes.values()
+    }
+
+    /// Queue the chunks within the provided (inclusive) range of chunk coordinates to be
+    /// built. The existing meshes are drawn until this happens.
+    pub fn request(
+        &mut self,
+        gen: &Arc<CBodyGenerator>,
+        min: Vector3<i32>,
+        max: Vector3<i32>,
+        mesh_options: MeshOptions,
+        pool: &WorkerPool,
+    ) {
+        for cx in min.x..=max.x {
+            for cy in min.y..=max.y {
+                for cz in min.z..=max.z {
+                    let key = (cx, cy, cz);
+                    let version = self.versions.get(&key).map_or(0, |v| v + 1);
+                    self.versions.insert(key, version);
+                    self.pending += 1;
+
+                    let gen = Arc::clone(gen);
+                    let sender = self.sender.clone();
+
+                    pool.execute(move || {
+                        let (vertices, indices) =
+                            CBody::build_mesh(&gen, Vector3::new(cx, cy, cz), mesh_options);
+
+                        // The body may have been destroyed while this was building
+                        let _ = sender.send(ChunkResult {
+                            key,
+                            version,
+                            vertices,
+                            indices,
+                        });
+                    });
+                }
+            }
+        }
+    }
+
+    /// Upload any chunks that have finished building
+    pub fn receive(&mut self, device: &wgpu::Device) {
+        for result in self.receiver.try_iter() {
+            self.pending -= 1;
+
+            // A newer version of this chunk has been requested
+            if self.versions.get(&result.key) != Some(&result.version) {
+                continue;
+            }
+
+            if result.indices.is_empty() {
+                self.meshes.remove(&result.key);
+            } else {
+                let mesh = Mesh::new(result.vertices, result.indices, device);
+                self.meshes.insert(result.key, mesh);
+            }
+        }
+    }
+}
+
+impl Default for ChunkSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,65 @@
+use crate::c_body::CBodyGenerator;
+use cgmath::Vector3;
+
+/// The density of a generator sampled at every point of a lattice, so each point is only
+/// sampled once when meshing
+pub struct DensityGrid {
+    /// The lowest point of the lattice
+    origin: Vector3<i32>,
+    /// How many points there are along each side
+    size: i32,
+    densities: Vec<f32>,
+}
+
+impl DensityGrid {
+    /// Sample the points from `origin` to `origin + size - 1` along each axis
+    pub fn sample(gen: &CBodyGenerator, origin: Vector3<i32>, size: i32) -> Self {
+        let mut densities = Vec::with_capacity((size * size * size) as usize);
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let point = origin + Vector3::new(x, y, z);
+                    densities.push(gen.get_density(point.cast().unwrap()));
+                }
+            }
+        }
+
+        Self {
+            origin,
+            size,
+            densities,
+        }
+    }
+
+    /// The density at a point on the lattice
+    pub fn get(&self, point: Vector3<i32>) -> f32 {
+        let local = point - self.origin;
+        self.densities[((local.x * self.size + local.y) * self.size + local.z) as usize]
+    }
+}
+
+/// Where the surface crosses the edge between two neighbouring points. The points are
+/// always interpolated in the same order, so each crossing is identical no matter which
+/// cell it was found from.
+pub fn edge_crossing(
+    a: Vector3<i32>,
+    density_a: f32,
+    b: Vector3<i32>,
+    density_b: f32,
+) -> Vector3<f32> {
+    let ((a, density_a), (b, density_b)) = if (a.x, a.y, a.z) <= (b.x, b.y, b.z) {
+        ((a, density_a), (b, density_b))
+    } else {
+        ((b, density_b), (a, density_a))
+    };
+
+    let t = if density_a != density_b {
+        density_a / (density_a - density_b)
+    } else {
+        0.5
+    };
+
+    let a: Vector3<f32> = a.cast().unwrap();
+    let b: Vector3<f32> = b.cast().unwrap();
+    a + (b - a) * t.clamp(0.0, 1.0)
+}
@@ -0,0 +1,119 @@
+use crate::c_body::CBodyGenerator;
+use crate::surface_nets;
+use crate::utils::{self, Vertex};
+use cgmath::{ElementWise, Matrix3, Vector3, Zero};
+
+/// Eigenvalues smaller than this fraction of the largest are ignored when solving for the
+/// position of a vertex. This keeps vertices on flat or curved surfaces near the middle of
+/// the crossings, rather than wherever the tangent planes happen to meet.
+const QEF_THRESHOLD: f32 = 0.1;
+
+/// Build the vertices and indices for a chunk of voxels with dual contouring. This joins
+/// cells in the same way as surface nets, but places each vertex where the tangent planes
+/// of the surface at the edge crossings meet, so sharp edges and corners are kept.
+pub fn build_mesh(gen: &CBodyGenerator, chunk: Vector3<i32>) -> (Vec<Vertex>, Vec<u32>) {
+    surface_nets::build_dual_mesh(gen, chunk, |gen, cell, crossings| {
+        let normals: Vec<Vector3<f32>> = crossings.iter().map(|p| gen.get_normal(*p)).collect();
+        let position = solve_qef(crossings, &normals);
+
+        // Keep the vertex inside its cell, so the mesh can't fold over itself
+        let min: Vector3<f32> = cell.cast().unwrap();
+        let max = min.add_element_wise(1.0);
+        Vector3::new(
+            position.x.clamp(min.x, max.x),
+            position.y.clamp(min.y, max.y),
+            position.z.clamp(min.z, max.z),
+        )
+    })
+}
+
+/// Find the point that is closest to all of the planes through `points` with `normals`,
+/// by minimising the quadratic error function. The solution is found relative to the
+/// average of the points, which it falls back to along any direction that is not
+/// constrained by the planes.
+pub fn solve_qef(points: &[Vector3<f32>], normals: &[Vector3<f32>]) -> Vector3<f32> {
+    let mass_point = points.iter().fold(Vector3::zero(), |sum, p| sum + p) / points.len() as f32;
+
+    // The normal equations (AᵀA)x = Aᵀb, with each row of A being a normal
+    let mut ata = Matrix3::zero();
+    let mut atb = Vector3::zero();
+    for (point, normal) in points.iter().zip(normals.iter()) {
+        ata += Matrix3::from_cols(normal * normal.x, normal * normal.y, normal * normal.z);
+        atb += normal * cgmath::dot(*normal, point - mass_point);
+    }
+
+    // Solve with the pseudo-inverse
+    let (values, vectors) = utils::symmetric_eigen(ata);
+    let largest = values.x.abs().max(values.y.abs()).max(values.z.abs());
+
+    let mut offset = Vector3::zero();
+    for i in 0..3 {
+        if values[i].abs() > largest * QEF_THRESHOLD && values[i] != 0.0 {
+            let vector = vectors[i];
+            offset += vector * (cgmath::dot(vector, atb) / values[i]);
+        }
+    }
+
+    mass_point + offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn qef_finds_a_cube_corner() {
+        // The edges of a cell cross the three faces of a cube whose corner is in the cell
+        let corner = Vector3::new(1.25, 2.5, 3.75);
+        let points = [
+            Vector3::new(1.25, 2.0, 3.0),
+            Vector3::new(1.25, 3.0, 4.0),
+            Vector3::new(1.0, 2.5, 3.0),
+            Vector3::new(2.0, 2.5, 4.0),
+            Vector3::new(1.0, 2.0, 3.75),
+            Vector3::new(2.0, 3.0, 3.75),
+        ];
+        let normals = [
+            Vector3::unit_x(),
+            Vector3::unit_x(),
+            Vector3::unit_y(),
+            Vector3::unit_y(),
+            Vector3::unit_z(),
+            Vector3::unit_z(),
+        ];
+
+        let vertex = solve_qef(&points, &normals);
+        assert!(
+            (vertex - corner).magnitude() < 1e-4,
+            "{:?} != {:?}",
+            vertex,
+            corner
+        );
+    }
+
+    #[test]
+    fn qef_keeps_the_mass_point_along_an_edge() {
+        // Two faces only fix the vertex onto their edge, along which it stays at the
+        // average of the points
+        let points = [
+            Vector3::new(1.0, 0.25, 0.0),
+            Vector3::new(1.0, 0.75, 1.0),
+            Vector3::new(0.5, 1.0, 0.0),
+            Vector3::new(0.0, 1.0, 1.0),
+        ];
+        let normals = [
+            Vector3::unit_x(),
+            Vector3::unit_x(),
+            Vector3::unit_y(),
+            Vector3::unit_y(),
+        ];
+
+        let vertex = solve_qef(&points, &normals);
+        assert!(
+            (vertex - Vector3::new(1.0, 1.0, 0.5)).magnitude() < 1e-4,
+            "{:?}",
+            vertex
+        );
+    }
+}
@@ -0,0 +1,349 @@
+use crate::c_body::{CBody, CBodyGenerator, MeshOptions, MeshType};
+use crate::scenario;
+use crate::utils::Vertex;
+use anyhow::*;
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Where bodies exported from the GUI are written to
+pub const EXPORT_DIRECTORY: &str = "exports";
+
+/// File formats that bodies can be exported to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// Wavefront OBJ, with vertex colours
+    Obj,
+    /// Binary little endian PLY
+    Ply,
+    /// Binary glTF 2.0
+    Gltf,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Obj, ExportFormat::Ply, ExportFormat::Gltf];
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "obj" => Ok(ExportFormat::Obj),
+            "ply" => Ok(ExportFormat::Ply),
+            "gltf" | "glb" => Ok(ExportFormat::Gltf),
+            _ => bail!("Unsupported export format: {}", name),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Obj => "obj",
+            ExportFormat::Ply => "ply",
+            ExportFormat::Gltf => "glb",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Obj => "OBJ",
+            ExportFormat::Ply => "PLY",
+            ExportFormat::Gltf => "glTF",
+        }
+    }
+}
+
+/// Export a body to a file within the provided directory, named after the body. Returns
+/// the path of the written file.
+pub fn export_body(
+    name: &str,
+    gen: &CBodyGenerator,
+    mesh_type: MeshType,
+    mesh_options: MeshOptions,
+    directory: &Path,
+    format: ExportFormat,
+) -> Result<PathBuf> {
+    create_dir_all(directory)?;
+
+    let file_name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = directory.join(format!("{}.{}", file_name, format.extension()));
+
+    let (vertices, indices) = CBody::build_geometry(gen, mesh_type, mesh_options);
+    export_mesh(&path, name, &vertices, &indices, format)?;
+
+    Ok(path)
+}
+
+/// Export every body in the default scenario, without needing a window
+pub fn export_scenario(directory: &Path, format: ExportFormat) -> Result<()> {
+    for body in scenario::default_scenario() {
+        let gen = CBodyGenerator::new(body.generator, &body.config);
+        let path = export_body(
+            &body.config.name,
+            &gen,
+            body.config.mesh_type,
+            MeshOptions::default(),
+            directory,
+            format,
+        )?;
+
+        log::info!("Exported '{}' to {}", body.config.name, path.display());
+    }
+
+    Ok(())
+}
+
+/// Write a mesh to a file in the provided format
+pub fn export_mesh(
+    path: &Path,
+    name: &str,
+    vertices: &[Vertex],
+    indices: &[u32],
+    format: ExportFormat,
+) -> Result<()> {
+    // glTF doesn't allow empty buffers, and an empty file isn't much use in any format
+    if vertices.is_empty() || indices.is_empty() {
+        bail!("'{}' has no geometry to export", name);
+    }
+
+    let mut writer = BufWriter::new(
+        File::create(path).with_context(|| format!("Unable to create {}", path.display()))?,
+    );
+
+    match format {
+        ExportFormat::Obj => write_obj(&mut writer, name, vertices, indices)?,
+        ExportFormat::Ply => write_ply(&mut writer, vertices, indices)?,
+        ExportFormat::Gltf => write_glb(&mut writer, name, vertices, indices)?,
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_obj<W: Write>(
+    writer: &mut W,
+    name: &str,
+    vertices: &[Vertex],
+    indices: &[u32],
+) -> Result<()> {
+    writeln!(writer, "# Exported from Eris")?;
+    writeln!(writer, "o {}", name)?;
+
+    for v in vertices {
+        writeln!(
+            writer,
+            "v {} {} {} {} {} {}",
+            v.position.x, v.position.y, v.position.z, v.color.x, v.color.y, v.color.z
+        )?;
+    }
+
+    // OBJ texture coordinates start from the bottom of the image
+    for v in vertices {
+        writeln!(writer, "vt {} {}", v.tex_coord.x, 1.0 - v.tex_coord.y)?;
+    }
+
+    for v in vertices {
+        writeln!(writer, "vn {} {} {}", v.normal.x, v.normal.y, v.normal.z)?;
+    }
+
+    // Indices are 1 based
+    for face in indices.chunks(3) {
+        let (a, b, c) = (face[0] + 1, face[1] + 1, face[2] + 1);
+        writeln!(writer, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?;
+    }
+
+    Ok(())
+}
+
+fn write_ply<W: Write>(writer: &mut W, vertices: &[Vertex], indices: &[u32]) -> Result<()> {
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format binary_little_endian 1.0")?;
+    writeln!(writer, "comment Exported from Eris")?;
+    writeln!(writer, "element vertex {}", vertices.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz", "s", "t"].iter() {
+        writeln!(writer, "property float {}", property)?;
+    }
+    for property in ["red", "green", "blue"].iter() {
+        writeln!(writer, "property uchar {}", property)?;
+    }
+    writeln!(writer, "element face {}", indices.len() / 3)?;
+    writeln!(writer, "property list uchar uint vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    for v in vertices {
+        let values = [
+            v.position.x,
+            v.position.y,
+            v.position.z,
+            v.normal.x,
+            v.normal.y,
+            v.normal.z,
+            v.tex_coord.x,
+            v.tex_coord.y,
+        ];
+        for value in values.iter() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        let color = [v.color.x, v.color.y, v.color.z];
+        for channel in color.iter() {
+            writer.write_all(&[(channel.clamp(0.0, 1.0) * 255.0).round() as u8])?;
+        }
+    }
+
+    for face in indices.chunks(3) {
+        writer.write_all(&[3])?;
+        for index in face {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a binary glTF file. The vertex attributes are each stored in their own
+/// section of a single buffer.
+fn write_glb<W: Write>(
+    writer: &mut W,
+    name: &str,
+    vertices: &[Vertex],
+    indices: &[u32],
+) -> Result<()> {
+    let mut buffer: Vec<u8> = Vec::new();
+
+    let positions = push_floats(
+        &mut buffer,
+        vertices
+            .iter()
+            .flat_map(|v| [v.position.x, v.position.y, v.position.z]),
+    );
+    let normals = push_floats(
+        &mut buffer,
+        vertices
+            .iter()
+            .flat_map(|v| [v.normal.x, v.normal.y, v.normal.z]),
+    );
+    let tex_coords = push_floats(
+        &mut buffer,
+        vertices.iter().flat_map(|v| [v.tex_coord.x, v.tex_coord.y]),
+    );
+    let colors = push_floats(
+        &mut buffer,
+        vertices
+            .iter()
+            .flat_map(|v| [v.color.x, v.color.y, v.color.z]),
+    );
+
+    let indices_offset = buffer.len();
+    for index in indices {
+        buffer.extend_from_slice(&index.to_le_bytes());
+    }
+    let indices_view = (indices_offset, buffer.len() - indices_offset);
+
+    // Positions require bounds
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for v in vertices {
+        let p = [v.position.x, v.position.y, v.position.z];
+        for k in 0..3 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    }
+    if vertices.is_empty() {
+        min = [0.0; 3];
+        max = [0.0; 3];
+    }
+
+    let view = |(offset, length): (usize, usize), target: u32| {
+        format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            offset, length, target
+        )
+    };
+
+    // 34962 is ARRAY_BUFFER, 34963 is ELEMENT_ARRAY_BUFFER
+    let views = [
+        view(positions, 34962),
+        view(normals, 34962),
+        view(tex_coords, 34962),
+        view(colors, 34962),
+        view(indices_view, 34963),
+    ]
+    .join(",");
+
+    // 5126 is FLOAT, 5125 is UNSIGNED_INT
+    let count = vertices.len();
+    let accessors = [
+        format!(
+            r#"{{"bufferView":0,"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+            count, min[0], min[1], min[2], max[0], max[1], max[2]
+        ),
+        format!(
+            r#"{{"bufferView":1,"componentType":5126,"count":{},"type":"VEC3"}}"#,
+            count
+        ),
+        format!(
+            r#"{{"bufferView":2,"componentType":5126,"count":{},"type":"VEC2"}}"#,
+            count
+        ),
+        format!(
+            r#"{{"bufferView":3,"componentType":5126,"count":{},"type":"VEC3"}}"#,
+            count
+        ),
+        format!(
+            r#"{{"bufferView":4,"componentType":5125,"count":{},"type":"SCALAR"}}"#,
+            indices.len()
+        ),
+    ]
+    .join(",");
+
+    let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"Eris"}},"scene":0,"#,
+            r#""scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0,"name":"{name}"}}],"#,
+            r#""meshes":[{{"name":"{name}","primitives":[{{"attributes":"#,
+            r#"{{"POSITION":0,"NORMAL":1,"TEXCOORD_0":2,"COLOR_0":3}},"indices":4,"material":0}}]}}],"#,
+            r#""materials":[{{"name":"{name}","pbrMetallicRoughness":"#,
+            r#"{{"baseColorFactor":[1,1,1,1],"metallicFactor":0,"roughnessFactor":1}}}}],"#,
+            r#""buffers":[{{"byteLength":{length}}}],"bufferViews":[{views}],"accessors":[{accessors}]}}"#
+        ),
+        name = name,
+        length = buffer.len(),
+        views = views,
+        accessors = accessors
+    );
+
+    // Chunks must be aligned to four bytes
+    json.push_str(&" ".repeat((4 - json.len() % 4) % 4));
+    buffer.resize(buffer.len() + (4 - buffer.len() % 4) % 4, 0);
+
+    let total_length = 12 + 8 + json.len() + 8 + buffer.len();
+
+    // Header
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(total_length as u32).to_le_bytes())?;
+
+    // JSON chunk
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(json.as_bytes())?;
+
+    // Binary chunk
+    writer.write_all(&(buffer.len() as u32).to_le_bytes())?;
+    writer.write_all(b"BIN\0")?;
+    writer.write_all(&buffer)?;
+
+    Ok(())
+}
+
+/// Append floats to a buffer, returning the (offset, length) of the section they were written to
+fn push_floats<I: Iterator<Item = f32>>(buffer: &mut Vec<u8>, values: I) -> (usize, usize) {
+    let offset = buffer.len();
+    for value in values {
+        buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    (offset, buffer.len() - offset)
+}
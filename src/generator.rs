@@ -0,0 +1,583 @@
+use crate::c_body::{MATERIAL_DARK_ROCK, MATERIAL_ROCK};
+use crate::heightmap::Heightmap;
+use crate::noise::{Noise, Random};
+use crate::scenario;
+use anyhow::*;
+use cgmath::{ElementWise, InnerSpace, Vector3};
+use std::fmt;
+use std::sync::Arc;
+
+/// How many steps are taken when searching for the height of the surface
+const SURFACE_SEARCH_STEPS: u32 = 16;
+
+/// How often the noise that deforms asteroids changes across the body, this is kept
+/// low so the shape is lumpy rather than rough
+const ASTEROID_NOISE_FREQUENCY: f32 = 1.5;
+const ASTEROID_NOISE_OCTAVES: u32 = 3;
+
+/// How far lobes blend into each other, relative to the radius
+const LOBE_BLEND: f32 = 0.3;
+
+/// How much the two lobes of a contact binary overlap, the neck between them is
+/// thicker when this is larger
+const LOBE_OVERLAP: f32 = 0.25;
+
+/// The range of crater radii, relative to the radius. Small craters are much more
+/// common than large ones.
+const MIN_CRATER_RADIUS: f32 = 0.08;
+const MAX_CRATER_RADIUS: f32 = 0.4;
+
+/// How deep a crater is compared to its radius
+const CRATER_DEPTH_RATIO: f32 = 0.3;
+
+/// The height and width of the rim around a crater, relative to its depth and radius
+const CRATER_RIM_HEIGHT: f32 = 0.2;
+const CRATER_RIM_WIDTH: f32 = 0.4;
+
+/// Generates the terrain of a body. Implement this to add new kinds of bodies, positions
+/// are always in the local space of the body.
+///
+/// Generators are sampled from worker threads, so they must be `Send + Sync`.
+pub trait BodyGenerator: Send + Sync + fmt::Debug {
+    /// The density of the terrain at a position, positive values are solid
+    fn density(&self, position: Vector3<f32>) -> f32;
+
+    /// The material of solid terrain at a position
+    fn material(&self, _position: Vector3<f32>) -> u8 {
+        MATERIAL_ROCK
+    }
+
+    /// The mean radius of the surface
+    fn surface_radius(&self) -> f32;
+
+    /// The distance from the centre of the body to its furthest point. No terrain is
+    /// generated beyond this.
+    fn bounds(&self) -> f32;
+
+    /// The smallest angle (in radians) between details on the surface. This is used as
+    /// the step when finding slopes.
+    fn detail_angle(&self) -> f32 {
+        1e-3
+    }
+
+    /// The height of the surface along a direction from the centre of the body
+    fn surface_height(&self, direction: Vector3<f32>) -> f32 {
+        find_surface(|p| self.density(p), direction, self.bounds())
+    }
+
+    /// The kind of generator and the values it was made with, this is recorded when a
+    /// body is saved
+    fn parameters(&self) -> GeneratorParameters;
+}
+
+/// The kind of a generator and the values it was made with, by name
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneratorParameters {
+    pub kind: String,
+    pub values: Vec<(String, f64)>,
+    /// The labels of the images the generator was made from, see [scenario::find_texture]
+    pub labels: Vec<(String, String)>,
+}
+
+impl GeneratorParameters {
+    pub fn new(kind: &str, values: &[(&str, f64)]) -> Self {
+        Self {
+            kind: kind.to_string(),
+            values: values
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect(),
+            labels: Vec::new(),
+        }
+    }
+
+    /// Record the label of an image the generator was made from
+    pub fn with_label(mut self, name: &str, label: &str) -> Self {
+        self.labels.push((name.to_string(), label.to_string()));
+        self
+    }
+
+    /// Create the generator these parameters were recorded from again
+    pub fn build(&self) -> Result<Box<dyn BodyGenerator>> {
+        Ok(match self.kind.as_str() {
+            "Sphere" => Box::new(SphereGenerator::new(self.value("radius")?)),
+            "Noise" => Box::new(NoiseGenerator::new(
+                self.value("radius")?,
+                self.value("amplitude")?,
+                self.value("frequency")?,
+                self.value("octaves")? as u32,
+                self.value("seed")? as u32,
+            )),
+            "Heightmap" => Box::new(HeightmapGenerator::load(
+                self.value("radius")?,
+                self.label("heightmap")?,
+                self.value("scale")?,
+            )?),
+            "Asteroid" => {
+                let lobes: Vec<Lobe> = (0..).map_while(|i| self.lobe(i)).collect();
+                if lobes.is_empty() {
+                    bail!("The Asteroid generator has no lobes");
+                }
+
+                Box::new(AsteroidGenerator::from_lobes(
+                    self.value("radius")?,
+                    lobes,
+                    self.value("roughness")?,
+                    self.value("craters")? as u32,
+                    self.value("seed")? as u32,
+                ))
+            }
+            kind => bail!(
+                "'{}' generators can't be created from their parameters",
+                kind
+            ),
+        })
+    }
+
+    fn find(&self, name: &str) -> Option<f32> {
+        self.values
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| *value as f32)
+    }
+
+    fn value(&self, name: &str) -> Result<f32> {
+        self.find(name)
+            .with_context(|| format!("The {} generator has no '{}'", self.kind, name))
+    }
+
+    fn label(&self, name: &str) -> Result<&str> {
+        self.labels
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, label)| label.as_str())
+            .with_context(|| format!("The {} generator has no '{}'", self.kind, name))
+    }
+
+    /// An asteroid lobe, as recorded by [AsteroidGenerator::parameters]
+    fn lobe(&self, index: usize) -> Option<Lobe> {
+        let vector = |name: &str| {
+            let component = |axis: &str| self.find(&format!("lobe{}.{}.{}", index, name, axis));
+            Some(Vector3::new(
+                component("x")?,
+                component("y")?,
+                component("z")?,
+            ))
+        };
+
+        Some(Lobe {
+            center: vector("center")?,
+            axes: vector("axes")?,
+        })
+    }
+}
+
+/// Binary search along a direction for the surface of a density field, up to a maximum
+/// distance from the centre
+pub fn find_surface<F: Fn(Vector3<f32>) -> f32>(
+    density: F,
+    direction: Vector3<f32>,
+    max_distance: f32,
+) -> f32 {
+    let mut low = 0.0;
+    let mut high = max_distance;
+
+    for _ in 0..SURFACE_SEARCH_STEPS {
+        let middle = (low + high) / 2.0;
+        if density(direction * middle) >= 0.0 {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    low
+}
+
+/// The density of a surface that is `height` from the centre along the direction of a
+/// position
+fn height_density(position: Vector3<f32>, height: impl Fn(Vector3<f32>) -> f32) -> f32 {
+    let distance = position.magnitude();
+    if distance > 0.0 {
+        height(position / distance) - distance
+    } else {
+        height(Vector3::unit_y())
+    }
+}
+
+/// A perfect sphere. The surface sits at half the radius of the body, leaving room for
+/// terrain to be added.
+#[derive(Clone, Debug)]
+pub struct SphereGenerator {
+    pub radius: f32,
+}
+
+impl SphereGenerator {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+}
+
+impl BodyGenerator for SphereGenerator {
+    fn density(&self, position: Vector3<f32>) -> f32 {
+        self.surface_radius() - position.magnitude()
+    }
+
+    fn surface_radius(&self) -> f32 {
+        self.radius / 2.0
+    }
+
+    fn bounds(&self) -> f32 {
+        self.surface_radius()
+    }
+
+    fn parameters(&self) -> GeneratorParameters {
+        GeneratorParameters::new("Sphere", &[("radius", self.radius as f64)])
+    }
+
+    fn surface_height(&self, _direction: Vector3<f32>) -> f32 {
+        self.surface_radius()
+    }
+}
+
+/// A sphere whose surface is raised and lowered by fractal noise, giving rolling hills
+/// and valleys
+#[derive(Clone, Debug)]
+pub struct NoiseGenerator {
+    pub radius: f32,
+    /// How far the surface is moved above or below its mean radius
+    pub amplitude: f32,
+    /// How many features there are around the body
+    pub frequency: f32,
+    pub octaves: u32,
+    noise: Noise,
+}
+
+impl NoiseGenerator {
+    pub fn new(radius: f32, amplitude: f32, frequency: f32, octaves: u32, seed: u32) -> Self {
+        Self {
+            radius,
+            amplitude,
+            frequency,
+            octaves,
+            noise: Noise::new(seed),
+        }
+    }
+}
+
+impl BodyGenerator for NoiseGenerator {
+    fn density(&self, position: Vector3<f32>) -> f32 {
+        height_density(position, |d| self.surface_height(d))
+    }
+
+    fn surface_radius(&self) -> f32 {
+        self.radius / 2.0
+    }
+
+    fn bounds(&self) -> f32 {
+        self.surface_radius() + self.amplitude
+    }
+
+    fn detail_angle(&self) -> f32 {
+        // Roughly the wavelength of the finest octave
+        1.0 / (self.frequency * (1 << self.octaves.min(16)) as f32)
+    }
+
+    fn parameters(&self) -> GeneratorParameters {
+        GeneratorParameters::new(
+            "Noise",
+            &[
+                ("radius", self.radius as f64),
+                ("amplitude", self.amplitude as f64),
+                ("frequency", self.frequency as f64),
+                ("octaves", self.octaves as f64),
+                ("seed", self.noise.seed as f64),
+            ],
+        )
+    }
+
+    fn surface_height(&self, direction: Vector3<f32>) -> f32 {
+        let noise = self.noise.sphere(direction, self.frequency, self.octaves);
+        self.surface_radius() + noise * self.amplitude
+    }
+}
+
+/// A sphere displaced by an equirectangular heightmap
+#[derive(Clone, Debug)]
+pub struct HeightmapGenerator {
+    pub radius: f32,
+    /// The label of the image the heightmap was loaded from
+    pub label: &'static str,
+    pub heightmap: Arc<Heightmap>,
+}
+
+impl HeightmapGenerator {
+    /// Load the heightmap from one of the images bodies can be made with, see
+    /// [scenario::find_texture]
+    pub fn load(radius: f32, label: &str, scale: f32) -> Result<Self> {
+        let (label, bytes) = scenario::find_texture(label)?;
+        let heightmap = Heightmap::from_bytes(bytes, scale)
+            .with_context(|| format!("Failed to load the heightmap '{}'", label))?;
+
+        Ok(Self {
+            radius,
+            label,
+            heightmap: Arc::new(heightmap),
+        })
+    }
+}
+
+impl BodyGenerator for HeightmapGenerator {
+    fn density(&self, position: Vector3<f32>) -> f32 {
+        height_density(position, |d| self.surface_height(d))
+    }
+
+    fn surface_radius(&self) -> f32 {
+        self.radius / 2.0
+    }
+
+    fn bounds(&self) -> f32 {
+        self.surface_radius() + self.heightmap.scale
+    }
+
+    fn detail_angle(&self) -> f32 {
+        self.heightmap.texel_angle()
+    }
+
+    fn parameters(&self) -> GeneratorParameters {
+        GeneratorParameters::new(
+            "Heightmap",
+            &[
+                ("radius", self.radius as f64),
+                ("scale", self.heightmap.scale as f64),
+            ],
+        )
+        .with_label("heightmap", self.label)
+    }
+
+    fn surface_height(&self, direction: Vector3<f32>) -> f32 {
+        self.surface_radius() + self.heightmap.sample(direction)
+    }
+}
+
+/// One of the ellipsoids that an asteroid is built from
+#[derive(Clone, Debug)]
+struct Lobe {
+    /// The centre of the lobe, relative to the radius
+    center: Vector3<f32>,
+    /// The scale of the lobe along each axis, relative to the radius
+    axes: Vector3<f32>,
+}
+
+impl Lobe {
+    fn density(&self, position: Vector3<f32>, radius: f32) -> f32 {
+        // Distance through the ellipsoid, scaled back to the radius
+        radius
+            - (position - self.center * radius)
+                .div_element_wise(self.axes)
+                .magnitude()
+    }
+
+    /// The distance from the centre of the body to the furthest point of the lobe,
+    /// relative to the radius
+    fn extent(&self) -> f32 {
+        self.center.magnitude() + self.axes.x.max(self.axes.y).max(self.axes.z)
+    }
+}
+
+/// A bowl left in the surface of an asteroid by an old impact, with a raised rim
+#[derive(Clone, Debug)]
+struct Crater {
+    center: Vector3<f32>,
+    radius: f32,
+    depth: f32,
+}
+
+impl Crater {
+    /// How much the crater changes the density at a position
+    fn density(&self, position: Vector3<f32>) -> f32 {
+        let distance = (position - self.center).magnitude() / self.radius;
+        if distance >= 1.0 + CRATER_RIM_WIDTH {
+            return 0.0;
+        }
+
+        let bowl = (1.0 - distance * distance).max(0.0);
+        let rim = (1.0 - ((distance - 1.0) / CRATER_RIM_WIDTH).powi(2)).max(0.0);
+
+        self.depth * (rim * CRATER_RIM_HEIGHT - bowl)
+    }
+}
+
+/// Small bodies that are not massive enough to pull themselves into a sphere, such as
+/// asteroids and comet nuclei. The shape is made of one or more ellipsoids blended
+/// together, deformed by low frequency noise and covered in craters.
+#[derive(Clone)]
+pub struct AsteroidGenerator {
+    pub radius: f32,
+    /// How much the surface is deformed by noise, relative to the radius
+    pub roughness: f32,
+    lobes: Vec<Lobe>,
+    craters: Vec<Crater>,
+    noise: Noise,
+}
+
+impl AsteroidGenerator {
+    /// A single lumpy ellipsoid. `axes` is the scale of the ellipsoid along each axis,
+    /// relative to the radius.
+    pub fn new(radius: f32, axes: Vector3<f32>, roughness: f32, craters: u32, seed: u32) -> Self {
+        let lobes = vec![Lobe {
+            center: Vector3::new(0.0, 0.0, 0.0),
+            axes,
+        }];
+
+        Self::from_lobes(radius, lobes, roughness, craters, seed)
+    }
+
+    /// Two ellipsoids resting against each other along the x axis, like many comets. The
+    /// second lobe is `companion` times the size of the first.
+    pub fn contact_binary(
+        radius: f32,
+        axes: Vector3<f32>,
+        companion: f32,
+        roughness: f32,
+        craters: u32,
+        seed: u32,
+    ) -> Self {
+        let separation = axes.x * (1.0 + companion) * (1.0 - LOBE_OVERLAP);
+
+        // Keep the centre of the volume at the centre of the body
+        let weight = companion.powi(3) / (1.0 + companion.powi(3));
+        let lobes = vec![
+            Lobe {
+                center: Vector3::new(-separation * weight, 0.0, 0.0),
+                axes,
+            },
+            Lobe {
+                center: Vector3::new(separation * (1.0 - weight), 0.0, 0.0),
+                axes: axes * companion,
+            },
+        ];
+
+        Self::from_lobes(radius, lobes, roughness, craters, seed)
+    }
+
+    fn from_lobes(radius: f32, lobes: Vec<Lobe>, roughness: f32, craters: u32, seed: u32) -> Self {
+        let mut generator = Self {
+            radius,
+            roughness,
+            lobes,
+            craters: Vec::new(),
+            noise: Noise::new(seed),
+        };
+
+        // Craters are pressed into the surface of the shape without any craters
+        let mut random = Random::new(seed.wrapping_add(1));
+        let surface_radius = generator.surface_radius();
+        let bounds = generator.bounds();
+        generator.craters = (0..craters)
+            .map(|_| {
+                let direction = random.direction();
+                let height = find_surface(|p| generator.shape_density(p), direction, bounds);
+                let size = MIN_CRATER_RADIUS
+                    + (MAX_CRATER_RADIUS - MIN_CRATER_RADIUS) * random.next_f32().powi(3);
+
+                Crater {
+                    center: direction * height,
+                    radius: size * surface_radius,
+                    depth: size * surface_radius * CRATER_DEPTH_RATIO,
+                }
+            })
+            .collect();
+
+        generator
+    }
+
+    /// The density of the lobes deformed by noise, before any craters
+    fn shape_density(&self, position: Vector3<f32>) -> f32 {
+        let radius = self.surface_radius();
+
+        // The noise is three dimensional, so there can be overhangs
+        let noise = self.noise.fractal(
+            position / radius * ASTEROID_NOISE_FREQUENCY,
+            ASTEROID_NOISE_OCTAVES,
+        );
+        let radius = radius * (1.0 + noise * self.roughness);
+
+        self.lobes
+            .iter()
+            .map(|lobe| lobe.density(position, radius))
+            .fold(None, |density: Option<f32>, lobe| match density {
+                Some(density) => Some(smooth_max(density, lobe, LOBE_BLEND * radius)),
+                None => Some(lobe),
+            })
+            .unwrap_or(-radius)
+    }
+}
+
+/// The larger of two values, rounded off where they are close to each other so shapes
+/// joined with it blend together
+fn smooth_max(a: f32, b: f32, blend: f32) -> f32 {
+    let h = (0.5 + 0.5 * (a - b) / blend).clamp(0.0, 1.0);
+    b + (a - b) * h + blend * h * (1.0 - h)
+}
+
+impl BodyGenerator for AsteroidGenerator {
+    fn density(&self, position: Vector3<f32>) -> f32 {
+        self.craters
+            .iter()
+            .fold(self.shape_density(position), |density, crater| {
+                density + crater.density(position)
+            })
+    }
+
+    fn material(&self, _position: Vector3<f32>) -> u8 {
+        MATERIAL_DARK_ROCK
+    }
+
+    fn surface_radius(&self) -> f32 {
+        self.radius / 2.0
+    }
+
+    fn bounds(&self) -> f32 {
+        let furthest = self.lobes.iter().map(Lobe::extent).fold(0.0, f32::max);
+        let rim = MAX_CRATER_RADIUS * CRATER_DEPTH_RATIO * CRATER_RIM_HEIGHT;
+
+        self.surface_radius() * (furthest * (1.0 + self.roughness) + LOBE_BLEND + rim)
+    }
+
+    fn parameters(&self) -> GeneratorParameters {
+        let mut parameters = GeneratorParameters::new(
+            "Asteroid",
+            &[
+                ("radius", self.radius as f64),
+                ("roughness", self.roughness as f64),
+                ("craters", self.craters.len() as f64),
+                ("seed", self.noise.seed as f64),
+            ],
+        );
+
+        for (i, lobe) in self.lobes.iter().enumerate() {
+            for (name, value) in [("center", lobe.center), ("axes", lobe.axes)].iter() {
+                for (axis, component) in [("x", value.x), ("y", value.y), ("z", value.z)].iter() {
+                    parameters
+                        .values
+                        .push((format!("lobe{}.{}.{}", i, name, axis), *component as f64));
+                }
+            }
+        }
+
+        parameters
+    }
+}
+
+impl fmt::Debug for AsteroidGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsteroidGenerator")
+            .field("radius", &self.radius)
+            .field("roughness", &self.roughness)
+            .field("lobes", &self.lobes)
+            .field("craters", &self.craters.len())
+            .field("noise", &self.noise)
+            .finish()
+    }
+}
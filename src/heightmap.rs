@@ -0,0 +1,82 @@
+use crate::sphere;
+use anyhow::*;
+use cgmath::num_traits::FloatConst;
+use cgmath::Vector3;
+use std::fmt;
+
+/// An equirectangular grayscale image that displaces the surface of a body. White is
+/// the highest point, black is the lowest.
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    /// Heights between 0 and 1
+    data: Vec<f32>,
+    /// The height of the highest point above the surface
+    pub scale: f32,
+}
+
+impl Heightmap {
+    /// Load a heightmap from the bytes of an image (such as a BMP or PNG)
+    pub fn from_bytes(bytes: &[u8], scale: f32) -> Result<Self> {
+        let img = image::load_from_memory(bytes)?;
+        Ok(Self::from_image(&img, scale))
+    }
+
+    /// Create a heightmap from an image. Colour images are converted to grayscale.
+    pub fn from_image(img: &image::DynamicImage, scale: f32) -> Self {
+        let luma = img.to_luma16();
+        let (width, height) = luma.dimensions();
+        let data = luma
+            .into_raw()
+            .into_iter()
+            .map(|h| h as f32 / u16::MAX as f32)
+            .collect();
+
+        Self {
+            width,
+            height,
+            data,
+            scale,
+        }
+    }
+
+    /// The angle (in radians) between neighbouring pixels along the equator
+    pub fn texel_angle(&self) -> f32 {
+        2.0 * f32::PI() / self.width as f32
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> f32 {
+        // Wrap around the sphere horizontally, but not past the poles
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.max(0).min(self.height as i64 - 1) as usize;
+
+        self.data[y * self.width as usize + x]
+    }
+
+    /// The height above the surface in a direction from the centre of the body
+    pub fn sample(&self, direction: Vector3<f32>) -> f32 {
+        let uv = sphere::equirectangular_uv(direction);
+
+        // Bilinear filtering, pixel centres are at half coordinates
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.pixel(x0, y0) * (1.0 - fx) + self.pixel(x0 + 1, y0) * fx;
+        let bottom = self.pixel(x0, y0 + 1) * (1.0 - fx) + self.pixel(x0 + 1, y0 + 1) * fx;
+
+        (top * (1.0 - fy) + bottom * fy) * self.scale
+    }
+}
+
+impl fmt::Debug for Heightmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heightmap")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("scale", &self.scale)
+            .finish()
+    }
+}
@@ -0,0 +1,375 @@
+use crate::c_body::CBodyGenerator;
+use crate::mesh::Mesh;
+use crate::sphere;
+use crate::utils::Vertex;
+use crate::worker::WorkerPool;
+use cgmath::num_traits::FloatConst;
+use cgmath::{InnerSpace, Vector3};
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+/// The number of quads along each side of a patch
+pub const PATCH_RESOLUTION: u32 = 8;
+
+/// Patches split once their error is larger than this many pixels on screen
+pub const SPLIT_THRESHOLD: f32 = 4.0;
+
+/// Patches merge once their error is smaller than this many pixels on screen. This is
+/// lower than the split threshold so patches don't flicker between the two.
+pub const MERGE_THRESHOLD: f32 = 2.0;
+
+/// Identifies a patch on the cube-sphere. Each face of the cube is the root of a
+/// quadtree, and (x, y) range from 0 to 2^depth on that face.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PatchKey {
+    pub face: usize,
+    pub depth: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl PatchKey {
+    /// The size of this patch on the face of the cube, faces range from -1 to 1
+    pub fn size(&self) -> f32 {
+        2.0 / (1 << self.depth) as f32
+    }
+
+    /// The (s, t) coordinates of the corner of this patch on the face of the cube
+    pub fn origin(&self) -> (f32, f32) {
+        let size = self.size();
+        (-1.0 + self.x as f32 * size, -1.0 + self.y as f32 * size)
+    }
+
+    /// The direction from the centre of the body to the centre of this patch
+    pub fn center(&self) -> Vector3<f32> {
+        let (s, t) = self.origin();
+        let half = self.size() / 2.0;
+        sphere::cube_to_sphere(sphere::cube_face_point(self.face, s + half, t + half))
+    }
+
+    pub fn children(&self) -> [PatchKey; 4] {
+        let child = |x, y| PatchKey {
+            face: self.face,
+            depth: self.depth + 1,
+            x: self.x * 2 + x,
+            y: self.y * 2 + y,
+        };
+
+        [child(0, 0), child(1, 0), child(0, 1), child(1, 1)]
+    }
+}
+
+struct Patch {
+    mesh: Option<Mesh>,
+    /// The generation of the terrain that the mesh was built from
+    generation: u32,
+    /// The latest generation that a mesh has been requested for
+    requested: u32,
+    split: bool,
+}
+
+impl Patch {
+    fn new() -> Self {
+        Self {
+            mesh: None,
+            generation: 0,
+            requested: 0,
+            split: false,
+        }
+    }
+}
+
+/// A finished patch built on a worker thread
+struct PatchResult {
+    key: PatchKey,
+    generation: u32,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+/// Level of detail for the surface of a body. The body is treated as a cube projected onto
+/// a sphere, with a quadtree on each face. Patches close to the camera are split into
+/// smaller patches, while distant patches are merged back together.
+pub struct QuadTree {
+    patches: HashMap<PatchKey, Patch>,
+    max_depth: u32,
+    generation: u32,
+    sender: Sender<PatchResult>,
+    receiver: Receiver<PatchResult>,
+}
+
+impl QuadTree {
+    pub fn new(max_depth: u32) -> Self {
+        let (sender, receiver) = channel();
+
+        let patches = (0..sphere::CUBE_FACES.len())
+            .map(|face| {
+                let key = PatchKey {
+                    face,
+                    depth: 0,
+                    x: 0,
+                    y: 0,
+                };
+                (key, Patch::new())
+            })
+            .collect();
+
+        Self {
+            patches,
+            max_depth,
+            generation: 1,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Whether the root patches have all been built, so the whole body can be drawn
+    pub fn is_ready(&self) -> bool {
+        self.patches
+            .iter()
+            .filter(|(k, _)| k.depth == 0)
+            .all(|(_, p)| p.mesh.is_some())
+    }
+
+    /// Whether every patch has been built from the latest terrain, including the patches
+    /// that were split around the camera
+    pub fn is_complete(&self) -> bool {
+        self.patches
+            .values()
+            .all(|p| p.generation == self.generation)
+    }
+
+    /// The terrain has changed, all patches will be rebuilt. The existing meshes are
+    /// drawn until this happens.
+    pub fn invalidate(&mut self) {
+        self.generation += 1;
+    }
+
+    /// Split and merge patches based on where the camera is, and upload any patches
+    /// that have finished building.
+    ///
+    /// `camera_position` is in the local space of the body, and `pixels_per_radian` is
+    /// the height of the screen divided by the field of view.
+    pub fn update(
+        &mut self,
+        camera_position: Vector3<f32>,
+        pixels_per_radian: f32,
+        gen: &Arc<CBodyGenerator>,
+        pool: &WorkerPool,
+        device: &wgpu::Device,
+    ) {
+        // Upload finished patches, the patch may have been merged away since it was requested
+        for result in self.receiver.try_iter() {
+            if let Some(patch) = self.patches.get_mut(&result.key) {
+                if result.generation > patch.generation {
+                    patch.mesh = Some(Mesh::new(result.vertices, result.indices, device));
+                    patch.generation = result.generation;
+                }
+            }
+        }
+
+        let roots: Vec<PatchKey> = self
+            .patches
+            .keys()
+            .filter(|k| k.depth == 0)
+            .copied()
+            .collect();
+
+        for key in roots {
+            self.update_patch(key, camera_position, pixels_per_radian, gen, pool);
+        }
+    }
+
+    fn update_patch(
+        &mut self,
+        key: PatchKey,
+        camera_position: Vector3<f32>,
+        pixels_per_radian: f32,
+        gen: &Arc<CBodyGenerator>,
+        pool: &WorkerPool,
+    ) {
+        // Make sure this patch has an up to date mesh
+        let generation = self.generation;
+        let patch = self.patches.get_mut(&key).unwrap();
+        if patch.requested < generation {
+            patch.requested = generation;
+            self.request_patch(key, gen, pool);
+        }
+
+        let error = Self::screen_space_error(&key, camera_position, gen.surface_radius())
+            * pixels_per_radian;
+
+        let patch = self.patches.get_mut(&key).unwrap();
+        if !patch.split && error > SPLIT_THRESHOLD && key.depth < self.max_depth {
+            patch.split = true;
+            for child in key.children().iter() {
+                self.patches.insert(*child, Patch::new());
+            }
+        } else if patch.split && error < MERGE_THRESHOLD {
+            patch.split = false;
+            for child in key.children().iter() {
+                self.remove_patch(*child);
+            }
+        }
+
+        if self.patches[&key].split {
+            for child in key.children().iter() {
+                self.update_patch(*child, camera_position, pixels_per_radian, gen, pool);
+            }
+        }
+    }
+
+    /// Remove a patch, and all patches below it
+    fn remove_patch(&mut self, key: PatchKey) {
+        if let Some(patch) = self.patches.remove(&key) {
+            if patch.split {
+                for child in key.children().iter() {
+                    self.remove_patch(*child);
+                }
+            }
+        }
+    }
+
+    /// The angle (in radians) that the error of a patch covers from the camera
+    fn screen_space_error(key: &PatchKey, camera_position: Vector3<f32>, radius: f32) -> f32 {
+        // A face of the cube covers roughly a quarter of the circumference
+        let width = key.size() / 2.0 * radius * f32::FRAC_PI_2();
+        let geometric_error = width / PATCH_RESOLUTION as f32;
+
+        // Distance to the closest point of the patch
+        let distance = (camera_position - key.center() * radius).magnitude() - width * 0.75;
+
+        geometric_error / distance.max(1e-3)
+    }
+
+    fn request_patch(&self, key: PatchKey, gen: &Arc<CBodyGenerator>, pool: &WorkerPool) {
+        let gen = Arc::clone(gen);
+        let sender = self.sender.clone();
+        let generation = self.generation;
+
+        pool.execute(move || {
+            let (vertices, indices) = build_patch(key, &gen);
+
+            // The body may have been destroyed while this was building
+            let _ = sender.send(PatchResult {
+                key,
+                generation,
+                vertices,
+                indices,
+            });
+        });
+    }
+
+    /// Collect the meshes that should be drawn. A split patch is drawn instead of its
+    /// children until all of them have been built, so there are no holes.
+    pub fn collect_meshes<'a>(&'a self, meshes: &mut Vec<&'a Mesh>) {
+        for (key, patch) in self.patches.iter() {
+            if key.depth == 0 {
+                self.collect_patch(*key, patch, meshes);
+            }
+        }
+    }
+
+    fn collect_patch<'a>(&'a self, key: PatchKey, patch: &'a Patch, meshes: &mut Vec<&'a Mesh>) {
+        let children = key.children();
+        let children_ready = patch.split
+            && children
+                .iter()
+                .all(|c| matches!(self.patches.get(c), Some(p) if p.mesh.is_some()));
+
+        if children_ready {
+            for child in children.iter() {
+                self.collect_patch(*child, &self.patches[child], meshes);
+            }
+        } else if let Some(mesh) = &patch.mesh {
+            meshes.push(mesh);
+        }
+    }
+}
+
+/// Build the mesh for a patch. A skirt hangs down from the edges of the patch to hide any
+/// cracks between neighbouring patches at different levels of detail.
+fn build_patch(key: PatchKey, gen: &CBodyGenerator) -> (Vec<Vertex>, Vec<u32>) {
+    let resolution = PATCH_RESOLUTION;
+    let (s0, t0) = key.origin();
+    let step = key.size() / resolution as f32;
+
+    // Sample an extra ring around the patch so normals can be calculated at the edges
+    let samples = resolution + 3;
+    let positions: Vec<Vector3<f32>> = (0..samples * samples)
+        .map(|n| {
+            let i = (n / samples) as f32 - 1.0;
+            let j = (n % samples) as f32 - 1.0;
+            let direction = sphere::cube_to_sphere(sphere::cube_face_point(
+                key.face,
+                s0 + i * step,
+                t0 + j * step,
+            ))
+            .normalize();
+
+            direction * gen.get_surface_height(direction)
+        })
+        .collect();
+
+    let sample = |i: u32, j: u32| positions[((i + 1) * samples + j + 1) as usize];
+
+    let mut vertices: Vec<Vertex> = Vec::new();
+    for i in 0..=resolution {
+        for j in 0..=resolution {
+            let position = sample(i, j);
+            let ds = positions[((i + 2) * samples + j + 1) as usize]
+                - positions[(i * samples + j + 1) as usize];
+            let dt = positions[((i + 1) * samples + j + 2) as usize]
+                - positions[((i + 1) * samples + j) as usize];
+
+            let direction = position.normalize();
+            vertices.push(Vertex::with_tangent(
+                position,
+                ds.cross(dt).normalize(),
+                sphere::equirectangular_uv(direction),
+                sphere::sphere_tangent(direction),
+            ));
+        }
+    }
+
+    let index = |i: u32, j: u32| i * (resolution + 1) + j;
+    let mut triangles: Vec<[u32; 3]> = Vec::new();
+    for i in 0..resolution {
+        for j in 0..resolution {
+            triangles.push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+            triangles.push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+        }
+    }
+
+    // Walk around the edge of the patch, the skirt is dropped by a few times the error
+    let skirt_depth = key.size() * gen.surface_radius() / resolution as f32;
+    let mut edge: Vec<u32> = Vec::new();
+    edge.extend((0..resolution).map(|i| index(i, 0)));
+    edge.extend((0..resolution).map(|j| index(resolution, j)));
+    edge.extend((1..=resolution).rev().map(|i| index(i, resolution)));
+    edge.extend((1..=resolution).rev().map(|j| index(0, j)));
+
+    let skirt_start = vertices.len() as u32;
+    for index in edge.iter() {
+        let mut vertex = vertices[*index as usize];
+        let direction = vertex.position.normalize();
+        vertex.position -= direction * skirt_depth;
+        vertices.push(vertex);
+    }
+
+    let count = edge.len() as u32;
+    for k in 0..count {
+        let (a, b) = (edge[k as usize], edge[((k + 1) % count) as usize]);
+        let (skirt_a, skirt_b) = (skirt_start + k, skirt_start + (k + 1) % count);
+
+        // The skirt is only seen edge on, so draw both sides
+        triangles.push([a, skirt_a, b]);
+        triangles.push([b, skirt_a, skirt_b]);
+        triangles.push([a, b, skirt_a]);
+        triangles.push([b, skirt_b, skirt_a]);
+    }
+
+    let indices = sphere::wrap_seams(&mut vertices, &triangles);
+    (vertices, indices)
+}
@@ -1,12 +1,37 @@
+mod atmosphere;
+mod background;
+mod biome;
 mod c_body;
 mod camera;
+mod chunks;
+mod density_grid;
+mod dual_contouring;
+mod export;
+mod generator;
+mod heightmap;
+mod lod;
 mod marching_cubes;
+mod mass;
+mod material;
 mod mesh;
+mod noise;
+mod normals;
+mod offscreen;
+mod post_process;
 mod render_pipeline;
+mod renderer;
+mod rotation;
+mod scenario;
+mod snapshot;
+mod sphere;
 mod state;
+mod surface_nets;
+mod terrain;
 mod texture;
 mod uniform_buffer;
 mod utils;
+mod voxels;
+mod worker;
 
 use winit::{
     event::*,
@@ -15,10 +40,79 @@ use winit::{
 };
 
 use futures::executor::block_on;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
+/// Find the value that follows a flag on the command line
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str())
+}
+
+/// Render the bodies to an image with the options from the command line
+async fn render(args: &[String], path: &Path) -> anyhow::Result<()> {
+    let (width, height) = match arg_value(args, "--size") {
+        Some(size) => parse_size(size)?,
+        None => (1280, 720),
+    };
+
+    let scenario = match arg_value(args, "--snapshot") {
+        Some(snapshot) => snapshot::load_snapshot(Path::new(snapshot))?,
+        None => scenario::default_scenario(),
+    };
+
+    offscreen::render_to_png(
+        scenario,
+        offscreen::DEFAULT_CAMERA_POSITION,
+        width,
+        height,
+        path,
+    )
+    .await
+}
+
+/// Parse an image size such as "1280x720"
+fn parse_size(size: &str) -> anyhow::Result<(u32, u32)> {
+    let (width, height) = size
+        .split_once('x')
+        .ok_or_else(|| anyhow::anyhow!("Expected a size like 1280x720, got '{}'", size))?;
+
+    Ok((width.parse()?, height.parse()?))
+}
+
 fn main() {
     env_logger::init();
+
+    // Export the bodies of the scenario and exit, without opening a window.
+    // Usage: eris --export <directory> [--format obj|ply|gltf]
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "--export") {
+        let directory =
+            PathBuf::from(arg_value(&args, "--export").unwrap_or(export::EXPORT_DIRECTORY));
+        let result = export::ExportFormat::from_name(arg_value(&args, "--format").unwrap_or("obj"))
+            .and_then(|format| export::export_scenario(&directory, format));
+
+        if let Err(e) = result {
+            eprintln!("Export failed: {:?}", e);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    // Render the scenario (or a snapshot) to an image and exit, without opening a window.
+    // Usage: eris --render <file.png> [--size <width>x<height>] [--snapshot <file>]
+    if let Some(path) = arg_value(&args, "--render") {
+        if let Err(e) = block_on(render(&args, Path::new(path))) {
+            eprintln!("Render failed: {:?}", e);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
     let event_loop = EventLoop::new();
 
     // Create a window to use
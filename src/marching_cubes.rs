@@ -1,3 +1,8 @@
+use crate::c_body::{material_color, CBodyGenerator, CHUNK_SIZE};
+use crate::density_grid::{edge_crossing, DensityGrid};
+use crate::utils::Vertex;
+use cgmath::{Vector2, Vector3};
+
 static TRI_TABLE: [[i32; 16]; 256] = [
     [-1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
     [0, 8, 3, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
@@ -282,3 +287,60 @@ static EDGE_VERTICES: [[usize; 2]; 12] = [
     [2, 6],
     [3, 7],
 ];
+
+/// Build the vertices and indices for a chunk of voxels with marching cubes. Each cell
+/// between eight points of the density field is triangulated from a table of the 256
+/// ways the surface can pass through it.
+pub fn build_mesh(gen: &CBodyGenerator, chunk: Vector3<i32>) -> (Vec<Vertex>, Vec<u32>) {
+    let origin = chunk * CHUNK_SIZE;
+    let grid = DensityGrid::sample(gen, origin, CHUNK_SIZE + 1);
+
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let cell = origin + Vector3::new(x, y, z);
+                let corners: Vec<Vector3<i32>> = INDEX_TO_VERTEX
+                    .iter()
+                    .map(|o| cell + Vector3::new(o[0] as i32, o[1] as i32, o[2] as i32))
+                    .collect();
+                let densities: Vec<f32> = corners.iter().map(|c| grid.get(*c)).collect();
+
+                // Corners outside of the terrain set their bit
+                let case = densities
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, d)| **d < 0.0)
+                    .fold(0, |case, (i, _)| case | 1 << i);
+
+                if case == 0 || case == 255 {
+                    continue;
+                }
+
+                // The surface is coloured by the first solid corner
+                let solid = densities.iter().position(|d| *d >= 0.0).unwrap();
+                let color = material_color(gen.get_material(corners[solid].cast().unwrap()));
+
+                for edge in TRI_TABLE[case].iter().take_while(|e| **e >= 0) {
+                    let [a, b] = EDGE_VERTICES[*edge as usize];
+                    let position =
+                        edge_crossing(corners[a], densities[a], corners[b], densities[b]);
+
+                    let mut vertex = Vertex::with_tex_coords(
+                        position,
+                        gen.get_normal(position),
+                        Vector2::new(0.0, 0.0),
+                    );
+                    vertex.color = color;
+
+                    indices.push(vertices.len() as u32);
+                    vertices.push(vertex);
+                }
+            }
+        }
+    }
+
+    (vertices, indices)
+}
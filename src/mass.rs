@@ -0,0 +1,254 @@
+use crate::c_body::{material_density, CBodyGenerator};
+use cgmath::num_traits::FloatConst;
+use cgmath::{InnerSpace, Matrix3, SquareMatrix, Vector3, Zero};
+
+/// How many points are sampled along each axis of a voxel when integrating its mass
+const SAMPLES_PER_VOXEL: i32 = 2;
+
+/// Where the mass of a body comes from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mass {
+    /// A mass that is set by hand, it is spread through the body by its shape and materials
+    Fixed(f32),
+    /// Found by filling the terrain with rock of this density. Other materials are
+    /// lighter or heavier than rock, see `material_density`.
+    Density(f32),
+}
+
+/// The mass of a body and how it is spread through its volume
+#[derive(Copy, Clone, Debug)]
+pub struct MassProperties {
+    pub mass: f32,
+    /// The density of rock within the body
+    pub density: f32,
+    /// The centre of mass, in the local space of the body
+    pub center_of_mass: Vector3<f32>,
+    /// The inertia tensor about the centre of mass
+    pub inertia: Matrix3<f32>,
+}
+
+impl MassProperties {
+    /// Find the mass properties of the terrain of a body
+    pub fn new(mass: Mass, gen: &CBodyGenerator) -> Self {
+        match mass {
+            Mass::Fixed(mass) => Self::from_voxels(gen, 1.0).with_mass(mass),
+            Mass::Density(density) => Self::from_voxels(gen, density),
+        }
+    }
+
+    /// Treat the body as a sphere of rock out to its surface. This is cheap, so it is used
+    /// until the terrain has been integrated.
+    pub fn estimate(mass: Mass, gen: &CBodyGenerator) -> Self {
+        let radius = gen.surface_radius();
+        let volume = sphere_volume(radius);
+        let (mass, density) = match mass {
+            Mass::Fixed(mass) => (mass, if volume > 0.0 { mass / volume } else { 0.0 }),
+            Mass::Density(density) => (volume * density, density),
+        };
+
+        Self {
+            mass,
+            density,
+            center_of_mass: Vector3::zero(),
+            inertia: Matrix3::from_value(0.4 * mass * radius * radius),
+        }
+    }
+
+    /// Integrate the density of the materials over the voxels of a body. Each voxel is
+    /// sampled at a few points, so voxels that are only partly solid are partly counted.
+    pub fn from_voxels(gen: &CBodyGenerator, density: f32) -> Self {
+        let extent = gen.extent() as f32;
+        let bounds = (
+            Vector3::new(-extent, -extent, -extent),
+            Vector3::new(extent, extent, extent),
+        );
+
+        MassIntegral::within(gen, density, bounds).into_properties(density)
+    }
+
+    /// Update the mass properties for a change to the terrain within a region, from the
+    /// mass within the region before and after the change. This is much cheaper than
+    /// integrating the whole body again.
+    pub fn with_change(self, before: MassIntegral, after: MassIntegral) -> Self {
+        let mut total = MassIntegral::from_properties(self);
+        total.mass += after.mass - before.mass;
+        total.moment += after.moment - before.moment;
+        total.inertia += after.inertia - before.inertia;
+
+        total.into_properties(self.density)
+    }
+
+    /// Scale the mass to a new total, keeping how it is spread through the body
+    pub fn with_mass(self, mass: f32) -> Self {
+        if self.mass <= 0.0 {
+            return Self { mass, ..self };
+        }
+
+        let scale = mass / self.mass;
+        Self {
+            mass,
+            density: self.density * scale,
+            center_of_mass: self.center_of_mass,
+            inertia: self.inertia * scale,
+        }
+    }
+}
+
+/// The mass within a region of a body, and its moments about the centre of the body
+#[derive(Copy, Clone, Debug)]
+pub struct MassIntegral {
+    mass: f32,
+    moment: Vector3<f32>,
+    inertia: Matrix3<f32>,
+}
+
+impl MassIntegral {
+    /// Integrate the density of the materials within bounds (in the local space of the
+    /// body). Samples are taken on the same grid whatever the bounds are, so the integrals
+    /// of a region before and after it changes can be swapped in the total.
+    pub fn within(
+        gen: &CBodyGenerator,
+        density: f32,
+        (min, max): (Vector3<f32>, Vector3<f32>),
+    ) -> Self {
+        let extent = gen.extent() * SAMPLES_PER_VOXEL;
+        let step = 1.0 / SAMPLES_PER_VOXEL as f32;
+        let volume = step * step * step;
+
+        // The range of samples with their centres in the bounds, and within the body
+        let first = |min: f32| ((min / step - 0.5).ceil() as i32).max(-extent);
+        let last = |max: f32| ((max / step - 0.5).floor() as i32).min(extent - 1);
+
+        let mut integral = Self {
+            mass: 0.0,
+            moment: Vector3::zero(),
+            inertia: Matrix3::zero(),
+        };
+        for x in first(min.x)..=last(max.x) {
+            for y in first(min.y)..=last(max.y) {
+                for z in first(min.z)..=last(max.z) {
+                    let position = (Vector3::new(x as f32, y as f32, z as f32)
+                        + Vector3::new(0.5, 0.5, 0.5))
+                        * step;
+
+                    let sample_mass =
+                        material_density(gen.get_material(position)) * density * volume;
+                    if sample_mass <= 0.0 {
+                        continue;
+                    }
+
+                    integral.mass += sample_mass;
+                    integral.moment += position * sample_mass;
+                    integral.inertia += point_inertia(position, sample_mass);
+
+                    // The inertia of the sample itself, as a small cube
+                    integral.inertia += Matrix3::from_value(sample_mass * step * step / 6.0);
+                }
+            }
+        }
+
+        integral
+    }
+
+    fn from_properties(properties: MassProperties) -> Self {
+        Self {
+            mass: properties.mass,
+            moment: properties.center_of_mass * properties.mass,
+            inertia: properties.inertia + point_inertia(properties.center_of_mass, properties.mass),
+        }
+    }
+
+    fn into_properties(self, density: f32) -> MassProperties {
+        if self.mass <= 0.0 {
+            return MassProperties {
+                mass: 0.0,
+                density,
+                center_of_mass: Vector3::zero(),
+                inertia: Matrix3::zero(),
+            };
+        }
+
+        // Move the inertia from the centre of the body to the centre of mass
+        let center_of_mass = self.moment / self.mass;
+        MassProperties {
+            mass: self.mass,
+            density,
+            center_of_mass,
+            inertia: self.inertia - point_inertia(center_of_mass, self.mass),
+        }
+    }
+}
+
+/// The volume of a sphere
+pub fn sphere_volume(radius: f32) -> f32 {
+    4.0 / 3.0 * f32::PI() * radius.powi(3)
+}
+
+/// The inertia tensor of a point mass about the origin
+fn point_inertia(position: Vector3<f32>, mass: f32) -> Matrix3<f32> {
+    let outer = Matrix3::from_cols(
+        position * position.x,
+        position * position.y,
+        position * position.z,
+    );
+    (Matrix3::identity() * position.magnitude2() - outer) * mass
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c_body::CBodyConfig;
+    use crate::generator::SphereGenerator;
+
+    /// A sphere of rock with its surface at a radius
+    fn sphere(radius: f32) -> CBodyGenerator {
+        CBodyGenerator::new(
+            Box::new(SphereGenerator::new(radius * 2.0)),
+            &CBodyConfig::default(),
+        )
+    }
+
+    #[test]
+    fn uniform_sphere() {
+        let radius = 8.0;
+        let density = 2.0;
+        let properties = MassProperties::new(Mass::Density(density), &sphere(radius));
+
+        // Voxels on the surface are only partly counted, so the integral is close to the
+        // analytic values rather than exact
+        let mass = sphere_volume(radius) * density;
+        assert!(
+            (properties.mass - mass).abs() < mass * 0.02,
+            "mass {} != {}",
+            properties.mass,
+            mass
+        );
+        assert!(properties.center_of_mass.magnitude() < 1e-3);
+
+        let inertia = 0.4 * mass * radius * radius;
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j { inertia } else { 0.0 };
+                assert!(
+                    (properties.inertia[i][j] - expected).abs() < inertia * 0.02,
+                    "inertia[{}][{}] {} != {}",
+                    i,
+                    j,
+                    properties.inertia[i][j],
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn estimate_is_a_uniform_sphere() {
+        let radius = 8.0;
+        let properties = MassProperties::estimate(Mass::Fixed(100.0), &sphere(radius));
+
+        assert_eq!(properties.mass, 100.0);
+        assert!((properties.density * sphere_volume(radius) - 100.0).abs() < 1e-3);
+        assert_eq!(properties.inertia[0][0], 0.4 * 100.0 * radius * radius);
+        assert_eq!(properties.inertia[0][1], 0.0);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::scenario;
+use crate::texture::{SamplerConfig, Texture, TextureKind};
+use crate::uniform_buffer::MaterialUniform;
+use anyhow::*;
+use image::{DynamicImage, Rgba, RgbaImage};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+/// How the surface of a body reflects light. These scale the values in the maps of the
+/// material, so without a map they are used as they are.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MaterialConfig {
+    /// 0 is a mirror, 1 is completely matte
+    pub roughness: f32,
+    /// 0 for rock, ice and other dielectrics, 1 for bare metal
+    pub metallic: f32,
+    /// How brightly the emissive map glows, past 1 it blooms
+    pub emissive: f32,
+}
+
+impl Default for MaterialConfig {
+    fn default() -> Self {
+        Self {
+            roughness: 0.9,
+            metallic: 0.0,
+            emissive: 1.0,
+        }
+    }
+}
+
+/// The labels of the images a material is made from, see [scenario::find_texture]. A
+/// body without a normal map is smooth, one without a roughness/metallic map has the
+/// roughness and metalness of its config everywhere, and one without an emissive map
+/// doesn't glow.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MaterialMaps {
+    pub albedo: &'static str,
+    pub normal: Option<&'static str>,
+    pub roughness_metallic: Option<&'static str>,
+    pub emissive: Option<&'static str>,
+}
+
+/// The maps a body is shaded with, bound together. These are the albedo, normals in
+/// tangent space (x follows the u texture coordinate and y follows v), roughness and
+/// metalness in the green and blue channels (as in glTF), and the light given off by the
+/// surface, which is added to the light it reflects.
+pub struct Material {
+    pub config: MaterialConfig,
+    pub maps: MaterialMaps,
+    albedo: Texture,
+    normal: Texture,
+    roughness_metallic: Texture,
+    emissive: Texture,
+    /// The config, for the shader
+    uniform_buffer: wgpu::Buffer,
+    /// How every map is sampled
+    sampler: SamplerConfig,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    /// Load the images of the maps into textures
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        maps: MaterialMaps,
+        config: MaterialConfig,
+        sampler: SamplerConfig,
+    ) -> Result<Self> {
+        let albedo =
+            Texture::from_image(device, queue, &load_map(maps.albedo)?, Some(maps.albedo))?;
+
+        // Normal maps and roughness are data rather than colours, so they are not sRGB
+        let normal = match maps.normal {
+            Some(label) => load_map(label)?,
+            None => solid_image([128, 128, 255, 255]),
+        };
+        let normal = Texture::from_image_with_kind(
+            device,
+            queue,
+            &normal,
+            Some(maps.normal.unwrap_or("flat_normal_map")),
+            TextureKind::Normal,
+        )?;
+
+        let roughness_metallic = match maps.roughness_metallic {
+            Some(label) => load_map(label)?,
+            None => solid_image([255, 255, 255, 255]),
+        };
+        let roughness_metallic = Texture::from_image_with_kind(
+            device,
+            queue,
+            &roughness_metallic,
+            Some(
+                maps.roughness_metallic
+                    .unwrap_or("uniform_roughness_metallic_map"),
+            ),
+            TextureKind::Data,
+        )?;
+
+        let emissive = match maps.emissive {
+            Some(label) => load_map(label)?,
+            None => solid_image([0, 0, 0, 255]),
+        };
+        let emissive = Texture::from_image(
+            device,
+            queue,
+            &emissive,
+            Some(maps.emissive.unwrap_or("black_emissive_map")),
+        )?;
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Material Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[MaterialUniform::new(&config)]),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            [&albedo, &normal, &roughness_metallic, &emissive],
+            &uniform_buffer,
+            &sampler.create_sampler(device),
+        );
+
+        Ok(Self {
+            config,
+            maps,
+            albedo,
+            normal,
+            roughness_metallic,
+            emissive,
+            uniform_buffer,
+            sampler,
+            bind_group,
+        })
+    }
+
+    /// Change how the maps are sampled, such as the filtering between mip levels
+    pub fn set_sampler(&mut self, device: &wgpu::Device, sampler: SamplerConfig) {
+        if sampler == self.sampler {
+            return;
+        }
+
+        self.sampler = sampler;
+        self.bind_group = Self::create_bind_group(
+            device,
+            [
+                &self.albedo,
+                &self.normal,
+                &self.roughness_metallic,
+                &self.emissive,
+            ],
+            &self.uniform_buffer,
+            &sampler.create_sampler(device),
+        );
+    }
+
+    /// Bind the maps and the config, every map is sampled alike
+    fn create_bind_group(
+        device: &wgpu::Device,
+        maps: [&Texture; 4],
+        uniform_buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        let mut entries: Vec<wgpu::BindGroupEntry> = maps
+            .iter()
+            .enumerate()
+            .flat_map(|(i, map)| {
+                vec![
+                    wgpu::BindGroupEntry {
+                        binding: i as u32 * 2,
+                        resource: wgpu::BindingResource::TextureView(&map.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: i as u32 * 2 + 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                ]
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: 8,
+            resource: uniform_buffer.as_entire_binding(),
+        });
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &Self::create_bind_group_layout(device),
+            entries: &entries,
+            label: Some("material_bind_group"),
+        })
+    }
+
+    /// A texture and sampler for each of the albedo, normal, roughness/metallic and
+    /// emissive maps, in that order, followed by the config
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries: Vec<wgpu::BindGroupLayoutEntry> = (0..4)
+            .flat_map(|i| {
+                vec![
+                    wgpu::BindGroupLayoutEntry {
+                        binding: i * 2,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: i * 2 + 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ]
+            })
+            .collect();
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 8,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("material_bind_group_layout"),
+        })
+    }
+}
+
+fn load_map(label: &str) -> Result<DynamicImage> {
+    let (_, bytes) = scenario::find_texture(label)?;
+    image::load_from_memory(bytes).with_context(|| format!("Failed to load '{}'", label))
+}
+
+/// A single pixel image, for maps that are the same everywhere
+fn solid_image(pixel: [u8; 4]) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba(pixel)))
+}
@@ -35,6 +35,14 @@ impl Mesh {
             num_vertices,
         }
     }
+
+    pub fn triangle_count(&self) -> u32 {
+        if self.num_indices == 0 {
+            self.num_vertices / 3
+        } else {
+            self.num_indices / 3
+        }
+    }
 }
 
 pub trait DrawMesh<'a, 'b>
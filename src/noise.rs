@@ -0,0 +1,148 @@
+use cgmath::num_traits::FloatConst;
+use cgmath::{InnerSpace, Vector3};
+use std::fmt;
+
+/// A small seeded xorshift generator, the same seed always produces the same numbers
+#[derive(Clone, Debug)]
+pub struct Random {
+    state: u32,
+}
+
+impl Random {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            state: seed.wrapping_mul(0x9E37_79B9) | 1,
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// A number between 0 and 1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// A direction that is equally likely to point anywhere on the unit sphere
+    pub fn direction(&mut self) -> Vector3<f32> {
+        let z = self.next_f32() * 2.0 - 1.0;
+        let angle = self.next_f32() * 2.0 * f32::PI();
+        let r = (1.0 - z * z).sqrt();
+
+        Vector3::new(r * angle.cos(), r * angle.sin(), z)
+    }
+}
+
+/// Seeded 3D gradient (Perlin) noise
+#[derive(Clone)]
+pub struct Noise {
+    pub seed: u32,
+    permutation: Vec<u8>,
+}
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        // Shuffle the table with a seeded generator, so the same seed always produces
+        // the same noise
+        let mut random = Random::new(seed);
+
+        let mut table: Vec<u8> = (0..=255).collect();
+        for i in (1..table.len()).rev() {
+            let j = random.next_u32() as usize % (i + 1);
+            table.swap(i, j);
+        }
+
+        // Repeat the table so lookups never need to wrap
+        let permutation = table.iter().chain(table.iter()).copied().collect();
+        Self { seed, permutation }
+    }
+
+    fn hash(&self, x: i32, y: i32, z: i32) -> u8 {
+        let p = &self.permutation;
+        let x = (x & 255) as usize;
+        let y = (y & 255) as usize;
+        let z = (z & 255) as usize;
+        p[p[p[x] as usize + y] as usize + z]
+    }
+
+    /// The dot product of one of twelve gradients with the offset from a lattice point
+    fn gradient(hash: u8, offset: Vector3<f32>) -> f32 {
+        let (x, y, z) = (offset.x, offset.y, offset.z);
+        match hash % 12 {
+            0 => x + y,
+            1 => -x + y,
+            2 => x - y,
+            3 => -x - y,
+            4 => x + z,
+            5 => -x + z,
+            6 => x - z,
+            7 => -x - z,
+            8 => y + z,
+            9 => -y + z,
+            10 => y - z,
+            _ => -y - z,
+        }
+    }
+
+    /// Sample the noise at a position, the result is roughly between -1 and 1
+    pub fn sample(&self, position: Vector3<f32>) -> f32 {
+        let floor = position.map(f32::floor);
+        let (x, y, z) = (floor.x as i32, floor.y as i32, floor.z as i32);
+        let local = position - floor;
+
+        // Smooth the interpolation so there are no creases between cells
+        let fade = local.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let offset = local - Vector3::new(dx as f32, dy as f32, dz as f32);
+            Self::gradient(self.hash(x + dx, y + dy, z + dz), offset)
+        };
+
+        let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), fade.x);
+        let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), fade.x);
+        let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), fade.x);
+        let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), fade.x);
+
+        lerp(lerp(x00, x10, fade.y), lerp(x01, x11, fade.y), fade.z)
+    }
+
+    /// Layered noise, each octave has twice the frequency and half the amplitude of the
+    /// last. The result is roughly between -1 and 1.
+    pub fn fractal(&self, position: Vector3<f32>, octaves: u32) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max = 0.0;
+
+        for octave in 0..octaves {
+            // Offset each octave so they don't line up at the origin
+            let offset = Vector3::new(17.3, -9.1, 5.7) * octave as f32;
+            total += self.sample(position * frequency + offset) * amplitude;
+            max += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+
+        if max > 0.0 {
+            total / max
+        } else {
+            0.0
+        }
+    }
+
+    /// Sample fractal noise on the surface of a unit sphere
+    pub fn sphere(&self, direction: Vector3<f32>, frequency: f32, octaves: u32) -> f32 {
+        self.fractal(direction.normalize() * frequency, octaves)
+    }
+}
+
+impl fmt::Debug for Noise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Noise").field("seed", &self.seed).finish()
+    }
+}
@@ -0,0 +1,136 @@
+use crate::c_body::CBodyGenerator;
+use crate::utils::Vertex;
+use cgmath::{InnerSpace, Vector3, Zero};
+use std::collections::HashMap;
+
+/// How the normals of voxel meshes are calculated
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NormalMode {
+    /// Each face keeps the normal it was built with
+    Flat,
+    /// The gradient of the density field at each vertex
+    Gradient,
+    /// The average normal of the faces around each vertex, weighted by the angle of
+    /// each face at that vertex
+    AngleWeighted,
+}
+
+impl NormalMode {
+    pub const ALL: [NormalMode; 3] = [
+        NormalMode::Flat,
+        NormalMode::Gradient,
+        NormalMode::AngleWeighted,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NormalMode::Flat => "Flat",
+            NormalMode::Gradient => "Gradient",
+            NormalMode::AngleWeighted => "Angle Weighted",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NormalOptions {
+    pub mode: NormalMode,
+    /// Merge identical vertices, so faces share them
+    pub weld: bool,
+}
+
+impl Default for NormalOptions {
+    fn default() -> Self {
+        Self {
+            mode: NormalMode::Gradient,
+            weld: true,
+        }
+    }
+}
+
+/// Recalculate the normals of a mesh built from a generator, and weld it if needed
+pub fn apply(
+    options: NormalOptions,
+    gen: &CBodyGenerator,
+    vertices: &mut Vec<Vertex>,
+    indices: &mut [u32],
+) {
+    match options.mode {
+        NormalMode::Flat => {}
+        NormalMode::Gradient => {
+            for vertex in vertices.iter_mut() {
+                vertex.normal = gen.get_normal(vertex.position);
+            }
+        }
+        NormalMode::AngleWeighted => angle_weighted_normals(vertices, indices),
+    }
+
+    if options.weld {
+        weld_vertices(vertices, indices);
+    }
+}
+
+/// Merge vertices that are exactly the same, updating the indices to match
+pub fn weld_vertices(vertices: &mut Vec<Vertex>, indices: &mut [u32]) {
+    let mut welded: Vec<Vertex> = Vec::with_capacity(vertices.len());
+    let mut lookup: HashMap<&[u8], u32> = HashMap::new();
+
+    let remap: Vec<u32> = vertices
+        .iter()
+        .map(|vertex| {
+            *lookup.entry(bytemuck::bytes_of(vertex)).or_insert_with(|| {
+                welded.push(*vertex);
+                welded.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    for index in indices.iter_mut() {
+        *index = remap[*index as usize];
+    }
+
+    *vertices = welded;
+}
+
+/// Set the normal of each vertex to the average of the faces around it, weighted by the
+/// angle of the face at the vertex. Vertices at the same position share a normal even if
+/// they have not been welded.
+pub fn angle_weighted_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let key = |v: &Vertex| {
+        let p = v.position;
+        (p.x.to_bits(), p.y.to_bits(), p.z.to_bits())
+    };
+
+    let mut sums: HashMap<(u32, u32, u32), Vector3<f32>> = HashMap::new();
+    for face in indices.chunks(3) {
+        let corners = [
+            vertices[face[0] as usize].position,
+            vertices[face[1] as usize].position,
+            vertices[face[2] as usize].position,
+        ];
+
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        if normal.magnitude2() == 0.0 {
+            continue;
+        }
+        let normal = normal.normalize();
+
+        for k in 0..3 {
+            let to_next = corners[(k + 1) % 3] - corners[k];
+            let to_previous = corners[(k + 2) % 3] - corners[k];
+            let angle = to_next.angle(to_previous).0;
+
+            let sum = sums
+                .entry(key(&vertices[face[k] as usize]))
+                .or_insert_with(Vector3::zero);
+            *sum += normal * angle;
+        }
+    }
+
+    for vertex in vertices.iter_mut() {
+        if let Some(sum) = sums.get(&key(vertex)) {
+            if sum.magnitude2() > 0.0 {
+                vertex.normal = sum.normalize();
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::camera::{Camera, Projection};
+use crate::renderer::{self, Renderer};
+use crate::scenario::ScenarioBody;
+use crate::state::State;
+use crate::worker::WorkerPool;
+use anyhow::*;
+use cgmath::num_traits::FloatConst;
+use cgmath::Vector3;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The format of offscreen images, this matches the PNG that is saved
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Where the camera is placed when rendering a scenario, looking at the origin
+pub const DEFAULT_CAMERA_POSITION: Vector3<f32> = Vector3::new(0.0, 200.0, 500.0);
+
+/// How long to wait for the meshes of every body to be built before giving up
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often the bodies are checked while they are being built
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Draw the bodies of a scenario into a texture and save it as a PNG, without opening a
+/// window. Any adapter is accepted, including software ones, so this works on servers
+/// without a display.
+pub async fn render_to_png(
+    scenario: Vec<ScenarioBody>,
+    camera_position: Vector3<f32>,
+    width: u32,
+    height: u32,
+    path: &Path,
+) -> Result<()> {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+        })
+        .await
+        .context("No graphics adapter was found")?;
+
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None, // Trace path
+        )
+        .await?;
+
+    // There is no swap chain, but the renderer is set up from the format and size of one
+    let sc_desc = wgpu::SwapChainDescriptor {
+        usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        format: OFFSCREEN_FORMAT,
+        width,
+        height,
+        present_mode: wgpu::PresentMode::Fifo,
+    };
+
+    let mut renderer = Renderer::new(
+        &device,
+        &queue,
+        &sc_desc,
+        renderer::supported_sample_counts(&adapter),
+    );
+
+    let mut camera = Camera::new(
+        camera_position,
+        Projection::new(
+            width,
+            height,
+            cgmath::Rad(70.0 / 180.0 * f32::PI()),
+            0.01,
+            1000.0,
+        ),
+        &device,
+    );
+    camera.look_at(Vector3::new(0.0, 0.0, 0.0));
+    camera.update_uniforms(&queue);
+
+    // Wait for every body to be built around the camera
+    let worker_pool = WorkerPool::new();
+    let mut bodies =
+        State::create_bodies(scenario, &device, &queue, &worker_pool, renderer.sampler);
+    renderer.update_lights(&bodies, &queue);
+
+    let start = Instant::now();
+    loop {
+        for body in bodies.iter_mut() {
+            body.update_meshes(&camera, height, &worker_pool, &device);
+        }
+
+        if bodies.iter().all(|b| b.is_complete()) {
+            break;
+        }
+
+        if start.elapsed() > GENERATION_TIMEOUT {
+            bail!("The bodies were not built within {:?}", GENERATION_TIMEOUT);
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: OFFSCREEN_FORMAT,
+        usage: sc_desc.usage,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // Rows of a copy must be aligned, the padding is removed once the image is read back
+    let unpadded_bytes_per_row = 4 * width;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padding = (align - unpadded_bytes_per_row % align) % align;
+    let padded_bytes_per_row = unpadded_bytes_per_row + padding;
+
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Offscreen Buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    renderer.render(&mut encoder, &view, &camera, &bodies);
+    encoder.copy_texture_to_buffer(
+        wgpu::TextureCopyView {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::BufferCopyView {
+            buffer: &output_buffer,
+            layout: wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: padded_bytes_per_row,
+                rows_per_image: height,
+            },
+        },
+        size,
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    // Read the image back to the CPU
+    let slice = output_buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    mapping.await?;
+
+    let mut pixels: Vec<u8> = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    for row in slice
+        .get_mapped_range()
+        .chunks_exact(padded_bytes_per_row as usize)
+    {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+    }
+    output_buffer.unmap();
+
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+
+    image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)
+        .with_context(|| format!("Failed to save {}", path.display()))?;
+
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use crate::render_pipeline::RenderPipelineBuilder;
+use crate::texture::Texture;
+use crate::uniform_buffer::{BlurUniform, PostProcessUniform, UniformBuffer, UniformBufferUtils};
+use cgmath::Vector2;
+
+/// The format the scene is drawn in, so it can be brighter than the screen can show
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// How many times the bright parts of the scene are blurred in each direction. Bloom
+/// is blurred at half resolution, so each pass spreads it further.
+const BLOOM_PASSES: u32 = 3;
+
+/// How the brightness of the scene is fit into the range of the screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Tonemapper {
+    /// A filmic curve with a gentle shoulder, colours saturate in the highlights
+    Aces,
+    Reinhard,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 2] = [Tonemapper::Aces, Tonemapper::Reinhard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tonemapper::Aces => "ACES",
+            Tonemapper::Reinhard => "Reinhard",
+        }
+    }
+}
+
+/// The options of the passes that run after the scene is drawn
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PostProcessSettings {
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    /// How bright a pixel needs to be before it blooms
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tonemapper: Tonemapper::Aces,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.6,
+        }
+    }
+}
+
+impl PostProcessSettings {
+    fn uniform(&self) -> PostProcessUniform {
+        PostProcessUniform {
+            exposure: self.exposure,
+            bloom_threshold: self.bloom_threshold,
+            bloom_intensity: self.bloom_intensity,
+            tonemapper: match self.tonemapper {
+                Tonemapper::Aces => 0,
+                Tonemapper::Reinhard => 1,
+            },
+        }
+    }
+}
+
+/// The scene is drawn into a floating point texture. Anything brighter than the threshold
+/// is blurred to make it glow, then the result is tonemapped onto the screen.
+pub struct PostProcess {
+    settings: PostProcessSettings,
+    hdr: Texture,
+    /// The bloom is blurred back and forth between these
+    bloom: [Texture; 2],
+    uniform_buffer: UniformBuffer<PostProcessUniform>,
+    /// Horizontal and vertical blurs
+    blur_uniforms: [UniformBuffer<BlurUniform>; 2],
+    threshold_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    tonemap_pipeline: wgpu::RenderPipeline,
+}
+
+impl PostProcess {
+    pub fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> Self {
+        let settings = PostProcessSettings::default();
+
+        let texture_layout = Texture::create_bind_group_layout(device);
+        let uniform_layout =
+            UniformBufferUtils::create_bind_group_layout(wgpu::ShaderStage::FRAGMENT, device);
+
+        let filter_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Filter Pipeline Layout"),
+            bind_group_layouts: &[&texture_layout, &uniform_layout],
+            push_constant_ranges: &[],
+        });
+        let tonemap_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tonemap Pipeline Layout"),
+            bind_group_layouts: &[&texture_layout, &texture_layout, &uniform_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Every pass draws over the whole screen, so none of them need a depth buffer
+        let threshold_pipeline = RenderPipelineBuilder::new(HDR_FORMAT, "Bloom Threshold Pipeline")
+            .with_vertex_shader(wgpu::include_spirv!("shaders/fullscreen.vert.spv"))
+            .with_fragment_shader(wgpu::include_spirv!("shaders/bloom_threshold.frag.spv"))
+            .with_layout(&filter_layout)
+            .with_vertex_buffers(Vec::new())
+            .without_depth()
+            .build(device)
+            .unwrap();
+
+        let blur_pipeline = RenderPipelineBuilder::new(HDR_FORMAT, "Blur Pipeline")
+            .with_vertex_shader(wgpu::include_spirv!("shaders/fullscreen.vert.spv"))
+            .with_fragment_shader(wgpu::include_spirv!("shaders/blur.frag.spv"))
+            .with_layout(&filter_layout)
+            .with_vertex_buffers(Vec::new())
+            .without_depth()
+            .build(device)
+            .unwrap();
+
+        let tonemap_pipeline = RenderPipelineBuilder::new(sc_desc.format, "Tonemap Pipeline")
+            .with_vertex_shader(wgpu::include_spirv!("shaders/fullscreen.vert.spv"))
+            .with_fragment_shader(wgpu::include_spirv!("shaders/tonemap.frag.spv"))
+            .with_layout(&tonemap_layout)
+            .with_vertex_buffers(Vec::new())
+            .without_depth()
+            .build(device)
+            .unwrap();
+
+        let uniform_buffer = UniformBuffer::new(
+            "Post Process Uniform Buffer",
+            wgpu::ShaderStage::FRAGMENT,
+            settings.uniform(),
+            device,
+        );
+
+        let (hdr, bloom, blur_uniforms) = Self::create_targets(device, sc_desc);
+
+        Self {
+            settings,
+            hdr,
+            bloom,
+            uniform_buffer,
+            blur_uniforms,
+            threshold_pipeline,
+            blur_pipeline,
+            tonemap_pipeline,
+        }
+    }
+
+    /// The textures are the size of the screen, so they are rebuilt when it changes
+    pub fn resize(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) {
+        let (hdr, bloom, blur_uniforms) = Self::create_targets(device, sc_desc);
+        self.hdr = hdr;
+        self.bloom = bloom;
+        self.blur_uniforms = blur_uniforms;
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        sc_desc: &wgpu::SwapChainDescriptor,
+    ) -> (Texture, [Texture; 2], [UniformBuffer<BlurUniform>; 2]) {
+        let hdr = Texture::create_render_target(
+            device,
+            sc_desc.width,
+            sc_desc.height,
+            HDR_FORMAT,
+            "hdr_texture",
+        );
+
+        let width = (sc_desc.width / 2).max(1);
+        let height = (sc_desc.height / 2).max(1);
+        let bloom = [
+            Texture::create_render_target(device, width, height, HDR_FORMAT, "bloom_texture_a"),
+            Texture::create_render_target(device, width, height, HDR_FORMAT, "bloom_texture_b"),
+        ];
+
+        let blur_uniform = |direction: Vector2<f32>| {
+            UniformBuffer::new(
+                "Blur Uniform Buffer",
+                wgpu::ShaderStage::FRAGMENT,
+                BlurUniform::new(direction),
+                device,
+            )
+        };
+        let blur_uniforms = [
+            blur_uniform(Vector2::new(1.0 / width as f32, 0.0)),
+            blur_uniform(Vector2::new(0.0, 1.0 / height as f32)),
+        ];
+
+        (hdr, bloom, blur_uniforms)
+    }
+
+    pub fn settings(&self) -> PostProcessSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: PostProcessSettings, queue: &wgpu::Queue) {
+        self.settings = settings;
+        self.uniform_buffer.data = settings.uniform();
+        queue.write_buffer(
+            &self.uniform_buffer.buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform_buffer.data]),
+        );
+    }
+
+    /// The texture the scene should be drawn into
+    pub fn hdr_view(&self) -> &wgpu::TextureView {
+        &self.hdr.view
+    }
+
+    /// Add bloom to the scene, then tonemap it into the target
+    pub fn apply(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        Self::fullscreen_pass(
+            encoder,
+            "Bloom Threshold Pass",
+            &self.bloom[0].view,
+            &self.threshold_pipeline,
+            &[
+                self.hdr.bind_group.as_ref().unwrap(),
+                &self.uniform_buffer.bind_group,
+            ],
+        );
+
+        for _ in 0..BLOOM_PASSES {
+            Self::fullscreen_pass(
+                encoder,
+                "Horizontal Blur Pass",
+                &self.bloom[1].view,
+                &self.blur_pipeline,
+                &[
+                    self.bloom[0].bind_group.as_ref().unwrap(),
+                    &self.blur_uniforms[0].bind_group,
+                ],
+            );
+            Self::fullscreen_pass(
+                encoder,
+                "Vertical Blur Pass",
+                &self.bloom[0].view,
+                &self.blur_pipeline,
+                &[
+                    self.bloom[1].bind_group.as_ref().unwrap(),
+                    &self.blur_uniforms[1].bind_group,
+                ],
+            );
+        }
+
+        Self::fullscreen_pass(
+            encoder,
+            "Tonemap Pass",
+            target,
+            &self.tonemap_pipeline,
+            &[
+                self.hdr.bind_group.as_ref().unwrap(),
+                self.bloom[0].bind_group.as_ref().unwrap(),
+                &self.uniform_buffer.bind_group,
+            ],
+        );
+    }
+
+    /// Draw a triangle over the whole target with a pipeline, every pixel is replaced
+    fn fullscreen_pass(
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        target: &wgpu::TextureView,
+        pipeline: &wgpu::RenderPipeline,
+        bind_groups: &[&wgpu::BindGroup],
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        for (i, bind_group) in bind_groups.iter().enumerate() {
+            render_pass.set_bind_group(i as u32, bind_group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+}
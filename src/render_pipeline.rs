@@ -7,8 +7,16 @@ pub struct RenderPipelineBuilder<'a> {
     vertex_shader_source: Option<wgpu::ShaderModuleDescriptor<'a>>,
     fragment_shader_source: Option<wgpu::ShaderModuleDescriptor<'a>>,
     texture_format: wgpu::TextureFormat,
+    vertex_buffers: Vec<wgpu::VertexBufferLayout<'a>>,
     pipeline_name: &'a str,
     primitive_topology: wgpu::PrimitiveTopology,
+    cull_mode: wgpu::CullMode,
+    depth_enabled: bool,
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+    color_blend: wgpu::BlendState,
+    alpha_blend: wgpu::BlendState,
+    sample_count: u32,
 }
 impl<'a> RenderPipelineBuilder<'a> {
     pub fn new(
@@ -20,8 +28,16 @@ impl<'a> RenderPipelineBuilder<'a> {
             vertex_shader_source: None,
             fragment_shader_source: None,
             texture_format,
+            vertex_buffers: vec![Vertex::desc()],
             pipeline_name,
             primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: wgpu::CullMode::Back,
+            depth_enabled: true,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            color_blend: wgpu::BlendState::REPLACE,
+            alpha_blend: wgpu::BlendState::REPLACE,
+            sample_count: 1,
         }
     }
 
@@ -46,12 +62,80 @@ impl<'a> RenderPipelineBuilder<'a> {
         self
     }
 
+    /// The layouts of the vertex buffers, by default this is a single buffer of `Vertex`
+    pub fn with_vertex_buffers(
+        &mut self,
+        vertex_buffers: Vec<wgpu::VertexBufferLayout<'a>>,
+    ) -> &mut Self {
+        self.vertex_buffers = vertex_buffers;
+        self
+    }
+
     #[allow(dead_code)]
     pub fn with_topology(&mut self, topology: wgpu::PrimitiveTopology) -> &mut Self {
         self.primitive_topology = topology;
         self
     }
 
+    /// Which faces are culled, by default back faces are
+    pub fn with_cull_mode(&mut self, cull_mode: wgpu::CullMode) -> &mut Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Draw without a depth buffer, so the pipeline can be used in passes that have none
+    pub fn without_depth(&mut self) -> &mut Self {
+        self.depth_enabled = false;
+        self
+    }
+
+    /// Whether the depth of what is drawn is written to the depth buffer, by default it is.
+    /// It is still tested against the depth buffer either way.
+    pub fn with_depth_write(&mut self, enabled: bool) -> &mut Self {
+        self.depth_write_enabled = enabled;
+        self
+    }
+
+    /// How the depth of what is drawn is compared with the depth buffer, by default it
+    /// passes where it is nearer (`Less`)
+    pub fn with_depth_compare(&mut self, compare: wgpu::CompareFunction) -> &mut Self {
+        self.depth_compare = compare;
+        self
+    }
+
+    /// Draw into multisampled targets, each pixel is covered by this many samples
+    pub fn with_sample_count(&mut self, sample_count: u32) -> &mut Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn with_blend(
+        &mut self,
+        color_blend: wgpu::BlendState,
+        alpha_blend: wgpu::BlendState,
+    ) -> &mut Self {
+        self.color_blend = color_blend;
+        self.alpha_blend = alpha_blend;
+        self
+    }
+
+    /// Blend the output of the fragment shader over what has already been drawn, using
+    /// its alpha
+    pub fn with_alpha_blending(&mut self) -> &mut Self {
+        self.with_blend(
+            wgpu::BlendState {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            wgpu::BlendState {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+        )
+    }
+
     pub fn build(&mut self, device: &wgpu::Device) -> Result<wgpu::RenderPipeline> {
         // Ensure layout
         if self.layout.is_none() {
@@ -91,26 +175,30 @@ impl<'a> RenderPipelineBuilder<'a> {
             vertex: wgpu::VertexState {
                 module: &vs_module,
                 entry_point: "main",
-                buffers: &[Vertex::desc()],
+                buffers: &self.vertex_buffers,
             },
             primitive: wgpu::PrimitiveState {
                 topology: self.primitive_topology,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back,
+                cull_mode: self.cull_mode,
                 polygon_mode: wgpu::PolygonMode::Fill,
             },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-                // Setting this to true requires Features::DEPTH_CLAMPING
-                clamp_depth: false,
-            }),
+            depth_stencil: if self.depth_enabled {
+                Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: self.depth_write_enabled,
+                    depth_compare: self.depth_compare,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                    // Setting this to true requires Features::DEPTH_CLAMPING
+                    clamp_depth: false,
+                })
+            } else {
+                None
+            },
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: self.sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
@@ -119,8 +207,8 @@ impl<'a> RenderPipelineBuilder<'a> {
                 entry_point: "main",
                 targets: &[wgpu::ColorTargetState {
                     format: self.texture_format,
-                    alpha_blend: wgpu::BlendState::REPLACE,
-                    color_blend: wgpu::BlendState::REPLACE,
+                    alpha_blend: self.alpha_blend.clone(),
+                    color_blend: self.color_blend.clone(),
                     write_mask: wgpu::ColorWrite::ALL,
                 }],
             }),
@@ -0,0 +1,478 @@
+use crate::background::Background;
+use crate::c_body::CBody;
+use crate::camera::Camera;
+use crate::material::Material;
+use crate::mesh::DrawMesh;
+use crate::post_process::{PostProcess, HDR_FORMAT};
+use crate::texture::{self, SamplerConfig, Texture};
+use crate::uniform_buffer::{LightUniform, Occluder, PointLight};
+use crate::{render_pipeline, uniform_buffer};
+
+/// How many samples each pixel can be drawn with, when the adapter supports it. See
+/// [supported_sample_counts].
+pub const SAMPLE_COUNTS: [u32; 3] = [1, 4, 8];
+
+const DEFAULT_SAMPLE_COUNT: u32 = 4;
+
+/// The sample counts in [SAMPLE_COUNTS] that the scene can be drawn with on an adapter.
+/// Every adapter can draw 1 and 4 samples into the HDR and depth formats. wgpu can't be
+/// asked about the others, so 8 samples are only offered on Vulkan and DirectX 12 GPUs,
+/// which support them for both formats.
+pub fn supported_sample_counts(adapter: &wgpu::Adapter) -> Vec<u32> {
+    let info = adapter.get_info();
+    let renderable = |format| {
+        adapter
+            .get_texture_format_features(format)
+            .allowed_usages
+            .contains(wgpu::TextureUsage::RENDER_ATTACHMENT)
+    };
+    let supports_8x = matches!(info.backend, wgpu::Backend::Vulkan | wgpu::Backend::Dx12)
+        && matches!(
+            info.device_type,
+            wgpu::DeviceType::DiscreteGpu | wgpu::DeviceType::IntegratedGpu
+        )
+        && renderable(HDR_FORMAT)
+        && renderable(Texture::DEPTH_FORMAT);
+
+    SAMPLE_COUNTS
+        .iter()
+        .copied()
+        .filter(|&count| count <= 4 || supports_8x)
+        .collect()
+}
+
+/// The pipelines that draw into the HDR texture, these are built for a sample count
+struct ScenePipelines {
+    c_body_pipeline: wgpu::RenderPipeline,
+    ocean_pipeline: wgpu::RenderPipeline,
+    atmosphere_pipeline: wgpu::RenderPipeline,
+    starfield_pipeline: wgpu::RenderPipeline,
+    skybox_pipeline: wgpu::RenderPipeline,
+}
+
+impl ScenePipelines {
+    fn new(device: &wgpu::Device, sample_count: u32) -> Self {
+        // Pipeline layout
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &Material::create_bind_group_layout(device),
+                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
+                        wgpu::ShaderStage::VERTEX,
+                        device,
+                    ),
+                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
+                        wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        device,
+                    ),
+                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
+                        wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        device,
+                    ),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let c_body_pipeline =
+            render_pipeline::RenderPipelineBuilder::new(HDR_FORMAT, "C Body Pipeline")
+                .with_vertex_shader(wgpu::include_spirv!("shaders/c_body_shader.vert.spv"))
+                .with_fragment_shader(wgpu::include_spirv!("shaders/c_body_shader.frag.spv"))
+                .with_layout(&render_pipeline_layout)
+                //.with_topology(wgpu::PrimitiveTopology::LineList)
+                .with_sample_count(sample_count)
+                .build(device)
+                .unwrap();
+
+        // Oceans are translucent, so they are blended over the bodies and don't hide
+        // anything behind them
+        let ocean_pipeline =
+            render_pipeline::RenderPipelineBuilder::new(HDR_FORMAT, "Ocean Pipeline")
+                .with_vertex_shader(wgpu::include_spirv!("shaders/ocean.vert.spv"))
+                .with_fragment_shader(wgpu::include_spirv!("shaders/ocean.frag.spv"))
+                .with_layout(&render_pipeline_layout)
+                .with_depth_write(false)
+                .with_alpha_blending()
+                .with_sample_count(sample_count)
+                .build(device)
+                .unwrap();
+
+        // Atmospheres have their own parameters in place of a texture
+        let atmosphere_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Atmosphere Pipeline Layout"),
+                bind_group_layouts: &[
+                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
+                        wgpu::ShaderStage::FRAGMENT,
+                        device,
+                    ),
+                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
+                        wgpu::ShaderStage::VERTEX,
+                        device,
+                    ),
+                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
+                        wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        device,
+                    ),
+                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
+                        wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        device,
+                    ),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        // The scattered light is added over the scene, which is dimmed by the air in front
+        // of it. Both sides of the shell are kept, so it can be seen from inside.
+        let premultiplied = wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let atmosphere_pipeline =
+            render_pipeline::RenderPipelineBuilder::new(HDR_FORMAT, "Atmosphere Pipeline")
+                .with_vertex_shader(wgpu::include_spirv!("shaders/atmosphere.vert.spv"))
+                .with_fragment_shader(wgpu::include_spirv!("shaders/atmosphere.frag.spv"))
+                .with_layout(&atmosphere_pipeline_layout)
+                .with_cull_mode(wgpu::CullMode::None)
+                .with_depth_write(false)
+                .with_blend(premultiplied.clone(), premultiplied)
+                .with_sample_count(sample_count)
+                .build(device)
+                .unwrap();
+
+        // The sky is drawn first, at the far plane, so every body is drawn over it
+        let camera_layout = uniform_buffer::UniformBufferUtils::create_bind_group_layout(
+            wgpu::ShaderStage::VERTEX,
+            device,
+        );
+        let starfield_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Starfield Pipeline Layout"),
+                bind_group_layouts: &[&camera_layout],
+                push_constant_ranges: &[],
+            });
+        let skybox_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skybox Pipeline Layout"),
+                bind_group_layouts: &[
+                    &camera_layout,
+                    &Texture::create_cube_bind_group_layout(device),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        // The light of overlapping stars adds up
+        let additive = wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        // The sky is seen from the inside, so it is not culled. It is at the far plane, so
+        // it needs to pass the depth test where nothing has been drawn.
+        let starfield_pipeline =
+            render_pipeline::RenderPipelineBuilder::new(HDR_FORMAT, "Starfield Pipeline")
+                .with_vertex_shader(wgpu::include_spirv!("shaders/starfield.vert.spv"))
+                .with_fragment_shader(wgpu::include_spirv!("shaders/starfield.frag.spv"))
+                .with_layout(&starfield_pipeline_layout)
+                .with_cull_mode(wgpu::CullMode::None)
+                .with_depth_write(false)
+                .with_depth_compare(wgpu::CompareFunction::LessEqual)
+                .with_blend(additive.clone(), additive)
+                .with_sample_count(sample_count)
+                .build(device)
+                .unwrap();
+
+        let skybox_pipeline =
+            render_pipeline::RenderPipelineBuilder::new(HDR_FORMAT, "Skybox Pipeline")
+                .with_vertex_shader(wgpu::include_spirv!("shaders/skybox.vert.spv"))
+                .with_fragment_shader(wgpu::include_spirv!("shaders/skybox.frag.spv"))
+                .with_layout(&skybox_pipeline_layout)
+                .with_cull_mode(wgpu::CullMode::None)
+                .with_depth_write(false)
+                .with_depth_compare(wgpu::CompareFunction::LessEqual)
+                .with_sample_count(sample_count)
+                .build(device)
+                .unwrap();
+
+        Self {
+            c_body_pipeline,
+            ocean_pipeline,
+            atmosphere_pipeline,
+            starfield_pipeline,
+            skybox_pipeline,
+        }
+    }
+}
+
+/// Draws the bodies of a scene. The target can be the swap chain of a window, or an
+/// offscreen texture when there is no display.
+///
+/// The scene is drawn in high dynamic range, then tonemapped into the target.
+pub struct Renderer {
+    pipelines: ScenePipelines,
+    background: Background,
+    /// How many samples each pixel of the scene is drawn with
+    sample_count: u32,
+    /// The sample counts the adapter supports
+    sample_counts: Vec<u32>,
+    depth_texture: texture::Texture,
+    /// The scene is drawn into this when multisampling, then resolved into the HDR texture
+    multisampled_target: Option<texture::Texture>,
+    pub post_process: PostProcess,
+    lights: uniform_buffer::UniformBuffer<LightUniform>,
+    /// Whether bodies cast shadows on each other
+    pub shadows: bool,
+    /// How the textures of bodies are sampled
+    pub sampler: SamplerConfig,
+}
+
+impl Renderer {
+    /// Create the pipelines for drawing into targets with the format and size of the
+    /// descriptor. The sample count can be changed to any of `sample_counts` later.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        sample_counts: Vec<u32>,
+    ) -> Self {
+        let pipelines = ScenePipelines::new(device, DEFAULT_SAMPLE_COUNT);
+
+        let background = Background::new(device, queue);
+
+        let (depth_texture, multisampled_target) =
+            Self::create_targets(device, sc_desc, DEFAULT_SAMPLE_COUNT);
+
+        let post_process = PostProcess::new(device, sc_desc);
+
+        let lights = uniform_buffer::UniformBuffer::new(
+            "Light Uniform Buffer",
+            wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+            LightUniform::new(&[], &[], true),
+            device,
+        );
+
+        Self {
+            pipelines,
+            background,
+            sample_count: DEFAULT_SAMPLE_COUNT,
+            sample_counts,
+            depth_texture,
+            multisampled_target,
+            post_process,
+            lights,
+            shadows: true,
+            sampler: SamplerConfig::default(),
+        }
+    }
+
+    /// The size of the target has changed, so the textures drawn into need to be rebuilt
+    pub fn resize(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) {
+        let (depth_texture, multisampled_target) =
+            Self::create_targets(device, sc_desc, self.sample_count);
+        self.depth_texture = depth_texture;
+        self.multisampled_target = multisampled_target;
+        self.post_process.resize(device, sc_desc);
+    }
+
+    /// The depth texture and, when multisampling, the colour target the scene is drawn into
+    fn create_targets(
+        device: &wgpu::Device,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        sample_count: u32,
+    ) -> (Texture, Option<Texture>) {
+        let depth_texture =
+            Texture::create_depth_texture(device, sc_desc, sample_count, "depth_texture");
+
+        let multisampled_target = if sample_count > 1 {
+            Some(Texture::create_multisampled_target(
+                device,
+                sc_desc.width,
+                sc_desc.height,
+                HDR_FORMAT,
+                sample_count,
+                "multisampled_target",
+            ))
+        } else {
+            None
+        };
+
+        (depth_texture, multisampled_target)
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// The sample counts the scene can be drawn with
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.sample_counts
+    }
+
+    /// Change how many samples each pixel of the scene is drawn with. Every pipeline that
+    /// draws the scene is built again, along with the targets it draws into. Counts that
+    /// the adapter doesn't support fall back to the default.
+    pub fn set_sample_count(
+        &mut self,
+        device: &wgpu::Device,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        sample_count: u32,
+    ) {
+        let sample_count = if self.sample_counts.contains(&sample_count) {
+            sample_count
+        } else {
+            DEFAULT_SAMPLE_COUNT
+        };
+        if sample_count == self.sample_count {
+            return;
+        }
+
+        self.sample_count = sample_count;
+        self.pipelines = ScenePipelines::new(device, sample_count);
+
+        let (depth_texture, multisampled_target) =
+            Self::create_targets(device, sc_desc, sample_count);
+        self.depth_texture = depth_texture;
+        self.multisampled_target = multisampled_target;
+    }
+
+    /// Light the scene from the stars. If there are more stars than lights, the most
+    /// luminous stars are used. Every body can eclipse the others.
+    pub fn update_lights(&mut self, bodies: &[CBody], queue: &wgpu::Queue) {
+        let mut lights: Vec<PointLight> = bodies
+            .iter()
+            .filter_map(|b| {
+                b.star.map(|s| {
+                    PointLight::new(
+                        b.center_of_mass_position(),
+                        s.color,
+                        s.luminosity,
+                        b.gen.surface_radius(),
+                    )
+                })
+            })
+            .collect();
+        // f32::total_cmp would need a newer compiler, a NaN luminosity just sorts anywhere
+        lights.sort_by(|a, b| {
+            b.intensity
+                .partial_cmp(&a.intensity)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        // Terrain can be added up to the extent of a body, so nothing closer is shadowed by it
+        let occluders: Vec<Occluder> = bodies
+            .iter()
+            .map(|b| {
+                Occluder::new(
+                    b.center_of_mass_position(),
+                    b.gen.surface_radius(),
+                    b.gen.extent() as f32,
+                )
+            })
+            .collect();
+
+        self.lights.data = LightUniform::new(&lights, &occluders, self.shadows);
+
+        queue.write_buffer(
+            &self.lights.buffer,
+            0,
+            bytemuck::cast_slice(&[self.lights.data]),
+        );
+    }
+
+    /// Draw the bodies into the target, replacing anything that was there
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        camera: &Camera,
+        bodies: &[CBody],
+    ) {
+        self.render_scene(encoder, camera, bodies);
+        self.post_process.apply(encoder, target);
+    }
+
+    /// Draw the bodies into the HDR texture
+    fn render_scene(&self, encoder: &mut wgpu::CommandEncoder, camera: &Camera, bodies: &[CBody]) {
+        // Each sample is drawn separately, then they are averaged into the HDR texture
+        let (attachment, resolve_target) = match &self.multisampled_target {
+            Some(target) => (&target.view, Some(self.post_process.hdr_view())),
+            None => (self.post_process.hdr_view(), None),
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Main Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 1.0,
+                    }),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                attachment: &self.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        // Render the sky
+        match &self.background {
+            Background::Starfield(mesh) => {
+                render_pass.set_pipeline(&self.pipelines.starfield_pipeline);
+                render_pass.set_bind_group(0, &camera.uniform_buffer.bind_group, &[]);
+                render_pass.draw_mesh(mesh);
+            }
+            Background::Skybox { mesh, texture } => {
+                render_pass.set_pipeline(&self.pipelines.skybox_pipeline);
+                render_pass.set_bind_group(0, &camera.uniform_buffer.bind_group, &[]);
+                render_pass.set_bind_group(1, texture.bind_group.as_ref().unwrap(), &[]);
+                render_pass.draw_mesh(mesh);
+            }
+        }
+
+        // Render bodies
+        render_pass.set_pipeline(&self.pipelines.c_body_pipeline);
+        render_pass.set_bind_group(1, &camera.uniform_buffer.bind_group, &[]);
+        render_pass.set_bind_group(3, &self.lights.bind_group, &[]);
+
+        for body in bodies.iter() {
+            render_pass.set_bind_group(0, &body.material.bind_group, &[]);
+            render_pass.set_bind_group(2, &body.uniform_buffer.bind_group, &[]);
+            for mesh in body.meshes() {
+                render_pass.draw_mesh(mesh);
+            }
+        }
+
+        // Oceans are drawn once everything solid is in the depth buffer
+        render_pass.set_pipeline(&self.pipelines.ocean_pipeline);
+        for body in bodies.iter() {
+            if let Some(ocean) = &body.ocean {
+                render_pass.set_bind_group(0, &body.material.bind_group, &[]);
+                render_pass.set_bind_group(2, &body.uniform_buffer.bind_group, &[]);
+                render_pass.draw_mesh(ocean);
+            }
+        }
+
+        // Atmospheres are see-through, so they are drawn last
+        render_pass.set_pipeline(&self.pipelines.atmosphere_pipeline);
+        render_pass.set_bind_group(1, &camera.uniform_buffer.bind_group, &[]);
+        render_pass.set_bind_group(3, &self.lights.bind_group, &[]);
+        for body in bodies.iter() {
+            if let Some(atmosphere) = &body.atmosphere {
+                render_pass.set_bind_group(0, &atmosphere.uniform_buffer.bind_group, &[]);
+                render_pass.set_bind_group(2, &body.uniform_buffer.bind_group, &[]);
+                render_pass.draw_mesh(&atmosphere.mesh);
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::utils::G;
+use cgmath::{InnerSpace, Matrix3, Quaternion, SquareMatrix, Vector3, Zero};
+
+/// How many steps the rotation of a body is split into every update. Fast spinning
+/// bodies drift from their true motion with fewer steps.
+const ROTATION_SUBSTEPS: u32 = 4;
+
+/// The orientation of a rigid body and how it is spinning
+#[derive(Copy, Clone, Debug)]
+pub struct RotationState {
+    pub orientation: Quaternion<f32>,
+    /// The angular velocity (radians per update), in the local space of the body
+    pub angular_velocity: Vector3<f32>,
+}
+
+impl RotationState {
+    /// Advance the rotation by a number of updates (which may be fractional) with Euler's
+    /// rotation equations, using the inertia tensor and a torque in the local space of the
+    /// body. Without torque the angular momentum is kept, so bodies that are not spinning
+    /// about a principal axis tumble and precess.
+    pub fn step(self, inertia: Matrix3<f32>, torque: Vector3<f32>, updates: f32) -> Self {
+        // A body without mass keeps spinning as it is
+        let inverse_inertia = inertia.invert().unwrap_or_else(Matrix3::zero);
+
+        let h = updates / ROTATION_SUBSTEPS as f32;
+        let mut state = self;
+        for _ in 0..ROTATION_SUBSTEPS {
+            // Runge-Kutta 4
+            let k1 = state.derivative(inertia, inverse_inertia, torque);
+            let k2 = state
+                .offset(k1, h * 0.5)
+                .derivative(inertia, inverse_inertia, torque);
+            let k3 = state
+                .offset(k2, h * 0.5)
+                .derivative(inertia, inverse_inertia, torque);
+            let k4 = state
+                .offset(k3, h)
+                .derivative(inertia, inverse_inertia, torque);
+
+            state = Self {
+                orientation: state.orientation
+                    + (k1.0 + k2.0 * 2.0 + k3.0 * 2.0 + k4.0) * (h / 6.0),
+                angular_velocity: state.angular_velocity
+                    + (k1.1 + k2.1 * 2.0 + k3.1 * 2.0 + k4.1) * (h / 6.0),
+            };
+            state.orientation = state.orientation.normalize();
+        }
+
+        state
+    }
+
+    /// How quickly the orientation and angular velocity are changing
+    fn derivative(
+        &self,
+        inertia: Matrix3<f32>,
+        inverse_inertia: Matrix3<f32>,
+        torque: Vector3<f32>,
+    ) -> (Quaternion<f32>, Vector3<f32>) {
+        let w = self.angular_velocity;
+
+        let orientation = self.orientation * Quaternion::from_sv(0.0, w) * 0.5;
+        let angular_velocity = inverse_inertia * (torque - w.cross(inertia * w));
+
+        (orientation, angular_velocity)
+    }
+
+    fn offset(&self, derivative: (Quaternion<f32>, Vector3<f32>), h: f32) -> Self {
+        Self {
+            orientation: self.orientation + derivative.0 * h,
+            angular_velocity: self.angular_velocity + derivative.1 * h,
+        }
+    }
+}
+
+/// The torque on a body from a distant mass, which pulls harder on the near side of the
+/// body than the far side. The offset to the mass and the inertia tensor are in the local
+/// space of the body.
+pub fn gravity_gradient_torque(
+    offset: Vector3<f32>,
+    mass: f32,
+    inertia: Matrix3<f32>,
+) -> Vector3<f32> {
+    let distance = offset.magnitude();
+    if distance <= 0.0 {
+        return Vector3::zero();
+    }
+
+    let direction = offset / distance;
+    direction.cross(inertia * direction) * (3.0 * G * mass / distance.powi(3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Rotation;
+
+    #[test]
+    fn torque_free_rotation_is_conserved() {
+        // Spinning about none of the principal axes, so the body tumbles
+        let inertia = Matrix3::from_diagonal(Vector3::new(1.0, 2.0, 3.0));
+        let mut state = RotationState {
+            orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            angular_velocity: Vector3::new(0.1, 0.05, 0.2),
+        };
+
+        // The angular momentum is constant in world space, the energy is the same in any
+        // space
+        let momentum = |s: RotationState| s.orientation.rotate_vector(inertia * s.angular_velocity);
+        let energy = |s: RotationState| 0.5 * s.angular_velocity.dot(inertia * s.angular_velocity);
+        let initial_momentum = momentum(state);
+        let initial_energy = energy(state);
+
+        for _ in 0..1000 {
+            state = state.step(inertia, Vector3::zero(), 1.0);
+        }
+
+        let drift = (momentum(state) - initial_momentum).magnitude() / initial_momentum.magnitude();
+        assert!(drift < 1e-3, "angular momentum drifted by {}", drift);
+
+        let drift = (energy(state) - initial_energy).abs() / initial_energy;
+        assert!(drift < 1e-3, "energy drifted by {}", drift);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::atmosphere::AtmosphereConfig;
+use crate::biome::BiomeConfig;
+use crate::c_body::{CBodyConfig, MeshType, StarConfig};
+use crate::generator::{
+    AsteroidGenerator, BodyGenerator, HeightmapGenerator, NoiseGenerator, SphereGenerator,
+};
+use crate::mass::{self, Mass};
+use crate::material::{MaterialConfig, MaterialMaps};
+use crate::utils::G;
+use anyhow::*;
+use cgmath::InnerSpace;
+use std::cmp::Ordering;
+
+/// The images that bodies can be drawn with, by label
+const TEXTURES: [(&str, &[u8]); 4] = [
+    ("sun.png", include_bytes!("images/sun.png")),
+    ("earth.png", include_bytes!("images/earth.png")),
+    ("moon1024.bmp", include_bytes!("images/moon1024.bmp")),
+    (
+        "moon1024_normal.png",
+        include_bytes!("images/moon1024_normal.png"),
+    ),
+];
+
+/// A body within a scenario, along with the generator for its terrain and the images it
+/// is drawn with
+pub struct ScenarioBody {
+    pub config: CBodyConfig,
+    pub generator: Box<dyn BodyGenerator>,
+    pub maps: MaterialMaps,
+}
+
+/// Find the image for a texture label, such as one saved in a snapshot
+pub fn find_texture(label: &str) -> Result<(&'static str, &'static [u8])> {
+    TEXTURES
+        .iter()
+        .find(|(l, _)| *l == label)
+        .copied()
+        .with_context(|| format!("Unknown texture '{}'", label))
+}
+
+/// The velocity needed to orbit a body of the provided mass at a radius
+pub fn orbital_velocity(mass: f32, radius: f32) -> f32 {
+    (G * mass / radius).sqrt()
+}
+
+/// The mass of a sphere of rock out to the surface of a body. The masses of bodies are
+/// found once their terrain is generated, this estimates them before then.
+pub fn sphere_mass(density: f32, gen: &dyn BodyGenerator) -> f32 {
+    mass::sphere_volume(gen.surface_radius()) * density
+}
+
+/// The bodies that the simulation starts with
+pub fn default_scenario() -> Vec<ScenarioBody> {
+    let sun_density = 58.0;
+    let planet_density = 11.0;
+    let asteroid_density = 0.002;
+
+    let sun = SphereGenerator::new(32.0);
+    let planet = NoiseGenerator::new(12.0, 1.0, 2.0, 4, 1);
+    let sun_mass = sphere_mass(sun_density, &sun);
+    let planet_mass = sphere_mass(planet_density, &planet);
+
+    let mut bodies = vec![
+        ScenarioBody {
+            config: CBodyConfig {
+                name: "Main Star".to_string(),
+                mass: Mass::Density(sun_density),
+                position: cgmath::Vector3::new(0.0, 0.0, 0.0),
+                velocity: cgmath::Vector3::new(0.0, 0.0, 0.0),
+                rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                angular_velocity: cgmath::Vector3::new(0.0, 0.0, 0.0),
+                mesh_type: MeshType::Quadtree { max_depth: 6 },
+                star: Some(StarConfig {
+                    color: cgmath::Vector3::new(1.0, 0.9, 0.75),
+                    luminosity: 40000.0,
+                    surface_brightness: 6.0,
+                }),
+                sea_level: None,
+                atmosphere: None,
+                biomes: None,
+                material: MaterialConfig {
+                    roughness: 1.0,
+                    metallic: 0.0,
+                    emissive: 1.0,
+                },
+            },
+            generator: Box::new(sun),
+            maps: MaterialMaps {
+                albedo: "sun.png",
+                normal: None,
+                roughness_metallic: None,
+                emissive: None,
+            },
+        },
+        ScenarioBody {
+            config: CBodyConfig {
+                name: "Companion Star".to_string(),
+                mass: Mass::Density(2.0),
+                position: cgmath::Vector3::new(0.0, 0.0, 450.0),
+                velocity: cgmath::Vector3::new(orbital_velocity(sun_mass, 450.0), 0.0, 0.0),
+                rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                angular_velocity: cgmath::Vector3::new(0.0, 0.0, 0.0),
+                mesh_type: MeshType::Icosphere { subdivisions: 5 },
+                star: Some(StarConfig {
+                    color: cgmath::Vector3::new(1.0, 0.55, 0.35),
+                    // 250 to 650 units from the planet, this lights it with between a sixth
+                    // of the main star's light and as much, enough for a second terminator
+                    luminosity: 64000.0,
+                    surface_brightness: 4.0,
+                }),
+                sea_level: None,
+                atmosphere: None,
+                biomes: None,
+                material: MaterialConfig {
+                    roughness: 1.0,
+                    metallic: 0.0,
+                    emissive: 1.0,
+                },
+            },
+            generator: Box::new(SphereGenerator::new(12.0)),
+            maps: MaterialMaps {
+                albedo: "sun.png",
+                normal: None,
+                roughness_metallic: None,
+                emissive: None,
+            },
+        },
+        ScenarioBody {
+            config: CBodyConfig {
+                name: "Planet".to_string(),
+                mass: Mass::Density(planet_density),
+                position: cgmath::Vector3::new(200.0, 0.0, 0.0),
+                velocity: cgmath::Vector3::new(0.0, 0.0, -orbital_velocity(sun_mass, 200.0)),
+                rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                angular_velocity: cgmath::Vector3::new(0.0, 0.002, 0.0),
+                mesh_type: MeshType::Voxel,
+                star: None,
+                sea_level: Some(6.0),
+                // Roughly the sky of the Earth, with the air stretched higher so it can be
+                // seen at this scale
+                atmosphere: Some(AtmosphereConfig {
+                    height: 1.2,
+                    rayleigh: cgmath::Vector3::new(0.19, 0.43, 1.06),
+                    rayleigh_scale_height: 0.25,
+                    mie: 0.25,
+                    mie_scale_height: 0.1,
+                    mie_anisotropy: 0.76,
+                }),
+                biomes: Some(BiomeConfig {
+                    seed: 3,
+                    // Found from where the stars are, once every body is placed
+                    star_distance: 0.0,
+                }),
+                material: MaterialConfig {
+                    roughness: 0.8,
+                    metallic: 0.0,
+                    emissive: 1.0,
+                },
+            },
+            generator: Box::new(planet),
+            maps: MaterialMaps {
+                albedo: "earth.png",
+                normal: None,
+                roughness_metallic: None,
+                emissive: None,
+            },
+        },
+        ScenarioBody {
+            config: CBodyConfig {
+                name: "Moon".to_string(),
+                mass: Mass::Density(0.024),
+                position: cgmath::Vector3::new(200.0 + 12.0, 0.0, 0.0),
+                velocity: cgmath::Vector3::new(0.0, 0.0, -orbital_velocity(planet_mass, 12.0)),
+                rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                angular_velocity: cgmath::Vector3::new(0.0, 0.0, 0.0),
+                mesh_type: MeshType::Voxel,
+                star: None,
+                sea_level: None,
+                atmosphere: None,
+                biomes: None,
+                material: MaterialConfig {
+                    roughness: 0.95,
+                    metallic: 0.0,
+                    emissive: 1.0,
+                },
+            },
+            generator: Box::new(SphereGenerator::new(2.0)),
+            maps: MaterialMaps {
+                albedo: "earth.png",
+                normal: None,
+                roughness_metallic: None,
+                emissive: None,
+            },
+        },
+        ScenarioBody {
+            config: CBodyConfig {
+                name: "Luna".to_string(),
+                mass: Mass::Density(0.23),
+                position: cgmath::Vector3::new(-350.0, 0.0, 0.0),
+                velocity: cgmath::Vector3::new(0.0, 0.0, orbital_velocity(sun_mass, 350.0)),
+                rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                angular_velocity: cgmath::Vector3::new(0.0, 0.0, 0.0),
+                mesh_type: MeshType::CubeSphere { subdivisions: 64 },
+                star: None,
+                sea_level: None,
+                atmosphere: None,
+                biomes: None,
+                material: MaterialConfig {
+                    roughness: 0.95,
+                    metallic: 0.0,
+                    emissive: 1.0,
+                },
+            },
+            generator: Box::new(HeightmapGenerator::load(16.0, "moon1024.bmp", 1.0).unwrap()),
+            maps: MaterialMaps {
+                albedo: "moon1024.bmp",
+                normal: Some("moon1024_normal.png"),
+                roughness_metallic: None,
+                emissive: None,
+            },
+        },
+        ScenarioBody {
+            config: CBodyConfig {
+                name: "Asteroid".to_string(),
+                mass: Mass::Density(asteroid_density),
+                position: cgmath::Vector3::new(0.0, 0.0, 120.0),
+                velocity: cgmath::Vector3::new(orbital_velocity(sun_mass, 120.0), 0.0, 0.0),
+                rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                angular_velocity: cgmath::Vector3::new(0.003, 0.012, 0.005),
+                mesh_type: MeshType::Voxel,
+                star: None,
+                sea_level: None,
+                atmosphere: None,
+                biomes: None,
+                material: MaterialConfig {
+                    roughness: 0.9,
+                    metallic: 0.0,
+                    emissive: 1.0,
+                },
+            },
+            generator: Box::new(AsteroidGenerator::new(
+                10.0,
+                cgmath::Vector3::new(1.6, 0.8, 1.0),
+                0.3,
+                12,
+                7,
+            )),
+            maps: MaterialMaps {
+                albedo: "moon1024.bmp",
+                normal: None,
+                roughness_metallic: None,
+                emissive: None,
+            },
+        },
+        ScenarioBody {
+            config: CBodyConfig {
+                name: "Comet".to_string(),
+                mass: Mass::Density(asteroid_density * 0.5),
+                position: cgmath::Vector3::new(0.0, 0.0, -150.0),
+                velocity: cgmath::Vector3::new(-orbital_velocity(sun_mass, 150.0), 0.0, 0.0),
+                rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                angular_velocity: cgmath::Vector3::new(0.006, 0.001, 0.004),
+                mesh_type: MeshType::Voxel,
+                star: None,
+                sea_level: None,
+                atmosphere: None,
+                biomes: None,
+                material: MaterialConfig {
+                    roughness: 0.5,
+                    metallic: 0.0,
+                    emissive: 1.0,
+                },
+            },
+            generator: Box::new(AsteroidGenerator::contact_binary(
+                8.0,
+                cgmath::Vector3::new(1.0, 0.8, 0.7),
+                0.7,
+                0.15,
+                8,
+                11,
+            )),
+            maps: MaterialMaps {
+                albedo: "moon1024.bmp",
+                normal: None,
+                roughness_metallic: None,
+                emissive: None,
+            },
+        },
+    ];
+
+    set_star_distances(&mut bodies);
+    bodies
+}
+
+/// Warm the biomes of each body by the star it gets the most light from, at the distance
+/// it starts from
+fn set_star_distances(bodies: &mut [ScenarioBody]) {
+    let stars: Vec<(cgmath::Vector3<f32>, f32)> = bodies
+        .iter()
+        .filter_map(|b| b.config.star.map(|s| (b.config.position, s.luminosity)))
+        .collect();
+
+    for body in bodies.iter_mut() {
+        let position = body.config.position;
+        if let Some(biomes) = &mut body.config.biomes {
+            // The light from a star falls off with the square of the distance
+            let brightest = stars
+                .iter()
+                .map(|(star, luminosity)| {
+                    let distance = (star - position).magnitude();
+                    (distance, luminosity / distance.powi(2))
+                })
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+
+            if let Some((distance, _)) = brightest {
+                biomes.star_distance = distance;
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// atmosphere.frag
+#version 450
+
+layout(location=0) in vec3 v_position;
+layout(location=1) in vec3 v_view_position;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform Atmosphere {
+    vec3 rayleigh;
+    float rayleigh_scale_height;
+    float mie;
+    float mie_scale_height;
+    float mie_anisotropy;
+    float surface_radius;
+    float atmosphere_radius;
+    float shell_radius;
+};
+
+layout(set=2, binding=0) uniform Model {
+    mat4 u_model;
+    vec3 u_emission_color;
+    float u_emission;
+};
+
+#include "lights.glsl"
+
+// How many samples are taken along the view ray, and along the ray to each light
+#define VIEW_SAMPLES 16
+#define LIGHT_SAMPLES 8
+
+const float PI = 3.14159265;
+
+// The distances along a ray to where it enters and leaves a sphere. The ray misses if
+// the second is smaller than the first.
+vec2 intersect_sphere(vec3 origin, vec3 dir, vec3 center, float radius) {
+    vec3 offset = origin - center;
+    float b = dot(offset, dir);
+    float c = dot(offset, offset) - radius * radius;
+    float discriminant = b * b - c;
+    if (discriminant < 0.0) {
+        return vec2(1.0, -1.0);
+    }
+
+    float s = sqrt(discriminant);
+    return vec2(-b - s, -b + s);
+}
+
+// The density of the air (x) and haze (y) at a point, relative to the surface
+vec2 density(vec3 position, vec3 center) {
+    float height = max(length(position - center) - surface_radius, 0.0);
+    return exp(-height / vec2(rayleigh_scale_height, mie_scale_height));
+}
+
+// How much of each colour is lost through a depth of air and haze
+vec3 transmittance(vec2 optical_depth) {
+    // Haze absorbs a little of the light as well as scattering it
+    vec3 extinction = rayleigh * optical_depth.x + vec3(mie * 1.1 * optical_depth.y);
+    return exp(-extinction);
+}
+
+float rayleigh_phase(float mu) {
+    return 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+}
+
+// Cornette-Shanks, light is mostly scattered forwards
+float mie_phase(float mu) {
+    float g = mie_anisotropy;
+    float g2 = g * g;
+    return 3.0 / (8.0 * PI) * ((1.0 - g2) * (1.0 + mu * mu))
+        / ((2.0 + g2) * pow(1.0 + g2 - 2.0 * g * mu, 1.5));
+}
+
+void main() {
+    vec3 center = (u_model * vec4(0.0, 0.0, 0.0, 1.0)).xyz;
+    vec3 origin = v_view_position;
+    vec3 dir = normalize(v_position - origin);
+
+    // From outside, the front of the shell covers the back, so only one is drawn
+    if (!gl_FrontFacing && length(origin - center) > shell_radius) {
+        discard;
+    }
+
+    // The part of the view ray inside the atmosphere, stopping at the surface
+    vec2 atmosphere_hit = intersect_sphere(origin, dir, center, atmosphere_radius);
+    float start = max(atmosphere_hit.x, 0.0);
+    float end = atmosphere_hit.y;
+    vec2 surface_hit = intersect_sphere(origin, dir, center, surface_radius);
+    if (surface_hit.x < surface_hit.y && surface_hit.x > 0.0) {
+        end = min(end, surface_hit.x);
+    }
+    if (end <= start) {
+        discard;
+    }
+
+    float step_length = (end - start) / float(VIEW_SAMPLES);
+    vec2 view_depth = vec2(0.0);
+    vec3 scattered = vec3(0.0);
+    for (int i = 0; i < VIEW_SAMPLES; i++) {
+        vec3 position = origin + dir * (start + step_length * (float(i) + 0.5));
+        vec2 sample_density = density(position, center) * step_length;
+        view_depth += sample_density;
+
+        for (uint j = 0u; j < light_count; j++) {
+            vec3 to_light = lights[j].position - position;
+            float distance_squared = max(dot(to_light, to_light), 0.0001);
+            vec3 light_dir = to_light * inversesqrt(distance_squared);
+
+            // The planet is in the way, it is night here
+            vec2 blocked = intersect_sphere(position, light_dir, center, surface_radius);
+            if (blocked.x < blocked.y && blocked.x > 0.0) {
+                continue;
+            }
+
+            // How much air the light passes through before reaching this point
+            float light_end = intersect_sphere(position, light_dir, center, atmosphere_radius).y;
+            float light_step = max(light_end, 0.0) / float(LIGHT_SAMPLES);
+            vec2 light_depth = vec2(0.0);
+            for (int k = 0; k < LIGHT_SAMPLES; k++) {
+                vec3 light_position = position + light_dir * (light_step * (float(k) + 0.5));
+                light_depth += density(light_position, center) * light_step;
+            }
+
+            // Light falls off with the square of the distance
+            vec3 radiance = lights[j].color * lights[j].intensity / distance_squared;
+            radiance *= shadow(position, lights[j]) * transmittance(view_depth + light_depth);
+
+            float mu = dot(dir, light_dir);
+            scattered += radiance * (rayleigh * sample_density.x * rayleigh_phase(mu)
+                + vec3(mie * sample_density.y * mie_phase(mu)));
+        }
+    }
+
+    // The scattered light is added, and whatever is behind is dimmed by the air in front
+    vec3 behind = transmittance(view_depth);
+    float alpha = 1.0 - (behind.r + behind.g + behind.b) / 3.0;
+    f_color = vec4(scattered, alpha);
+}
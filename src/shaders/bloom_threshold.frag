@@ -0,0 +1,26 @@
+// bloom_threshold.frag
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_source;
+layout(set=0, binding=1) uniform sampler s_source;
+
+layout(set=1, binding=0) uniform PostProcess {
+    float exposure;
+    float bloom_threshold;
+    float bloom_intensity;
+    uint tonemapper;
+};
+
+void main() {
+    vec3 color = texture(sampler2D(t_source, s_source), v_tex_coords).rgb;
+
+    // Only the part of the light above the threshold blooms
+    float brightness = max(color.r, max(color.g, color.b));
+    float contribution = max(brightness - bloom_threshold, 0.0) / max(brightness, 0.0001);
+
+    f_color = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,28 @@
+// blur.frag
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_source;
+layout(set=0, binding=1) uniform sampler s_source;
+
+layout(set=1, binding=0) uniform Blur {
+    // The distance between samples, in texture coordinates
+    vec2 direction;
+};
+
+// Gaussian weights, the centre sample followed by each side
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec3 result = texture(sampler2D(t_source, s_source), v_tex_coords).rgb * weights[0];
+    for (int i = 1; i < 5; i++) {
+        vec2 offset = direction * float(i);
+        result += texture(sampler2D(t_source, s_source), v_tex_coords + offset).rgb * weights[i];
+        result += texture(sampler2D(t_source, s_source), v_tex_coords - offset).rgb * weights[i];
+    }
+
+    f_color = vec4(result, 1.0);
+}
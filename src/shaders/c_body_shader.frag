@@ -4,34 +4,134 @@
 layout(location=0) in vec2 v_tex_coords;
 layout(location=1) in vec3 v_normal;
 layout(location=2) in vec3 v_position;
+layout(location=3) in vec3 v_tangent;
+layout(location=4) in vec3 v_view_position;
 
-layout(set = 0, binding = 0) uniform texture2D t_diffuse;
-layout(set = 0, binding = 1) uniform sampler s_diffuse;
+// The maps of the material
+layout(set = 0, binding = 0) uniform texture2D t_albedo;
+layout(set = 0, binding = 1) uniform sampler s_albedo;
+layout(set = 0, binding = 2) uniform texture2D t_normal;
+layout(set = 0, binding = 3) uniform sampler s_normal;
+layout(set = 0, binding = 4) uniform texture2D t_roughness_metallic;
+layout(set = 0, binding = 5) uniform sampler s_roughness_metallic;
+layout(set = 0, binding = 6) uniform texture2D t_emissive;
+layout(set = 0, binding = 7) uniform sampler s_emissive;
+
+// The maps are scaled by these
+layout(set = 0, binding = 8) uniform Material {
+    float u_roughness;
+    float u_metallic;
+    float u_emissive;
+};
 
 layout(location=0) out vec4 f_color;
 
-layout(set=3, binding=0) uniform Light {
-    vec3 light_position;
-    vec3 light_color;
+layout(set=2, binding=0) uniform Model {
+    mat4 u_model;
+    vec3 u_emission_color;
+    float u_emission;
 };
 
-void main() {
-    vec4 object_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords);
+#include "lights.glsl"
+
+const float PI = 3.14159265;
+
+// Trowbridge-Reitz (GGX), how many microfacets face halfway between the view and light
+float distribution(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / max(PI * d * d, 0.0001);
+}
+
+// Smith with Schlick-GGX, how many microfacets are hidden by others
+float geometry(float n_dot_v, float n_dot_l, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float view = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    float light = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return view * light;
+}
 
-    // We don't need (or want) much ambient light, so 0.1 is fine
-    float ambient_strength = 0.1;
-    vec3 ambient_color = light_color * ambient_strength;
+// Schlick, surfaces reflect more at grazing angles
+vec3 fresnel(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
 
-    // Diffuse
+// The normal of the surface, bent by the normal map
+vec3 surface_normal() {
     vec3 normal = normalize(v_normal);
-    vec3 light_dir = normalize(light_position - v_position);
 
-    float diffuse_strength = max(dot(normal, light_dir), 0.0);
-    vec3 diffuse_color = light_color * diffuse_strength;
+    // Without a tangent the texture coordinates don't follow the surface
+    if (dot(v_tangent, v_tangent) < 0.000001) {
+        return normal;
+    }
+
+    vec3 tangent = normalize(v_tangent - normal * dot(v_tangent, normal));
+    vec3 bitangent = cross(normal, tangent);
+    vec3 mapped = texture(sampler2D(t_normal, s_normal), v_tex_coords).xyz * 2.0 - 1.0;
+    return normalize(mat3(tangent, bitangent, normal) * mapped);
+}
+
+void main() {
+    vec4 object_color = texture(sampler2D(t_albedo, s_albedo), v_tex_coords);
+
+    // The light the surface gives off itself, such as city lights or lava
+    vec3 emissive = texture(sampler2D(t_emissive, s_emissive), v_tex_coords).rgb * u_emissive;
+
+    // Stars are not lit by anything else
+    if (u_emission > 0.0) {
+        f_color = vec4(u_emission_color * u_emission * object_color.xyz + emissive, object_color.a);
+        return;
+    }
+
+    vec3 albedo = object_color.xyz;
+    vec4 roughness_metallic =
+        texture(sampler2D(t_roughness_metallic, s_roughness_metallic), v_tex_coords);
+    float roughness = clamp(roughness_metallic.g * u_roughness, 0.04, 1.0);
+    float metallic = roughness_metallic.b * u_metallic;
+
+    vec3 normal = surface_normal();
+    vec3 view_dir = normalize(v_view_position - v_position);
+    float n_dot_v = max(dot(normal, view_dir), 0.0001);
+
+    // Dielectrics reflect about 4% of light head on, metals reflect their own colour
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+
+    vec3 light = vec3(0.0);
+    for (uint i = 0u; i < light_count; i++) {
+        vec3 to_light = lights[i].position - v_position;
+        float distance_squared = max(dot(to_light, to_light), 0.0001);
+
+        // Light falls off with the square of the distance
+        vec3 radiance = lights[i].color * lights[i].intensity / distance_squared;
+
+        // We don't need (or want) much ambient light, so 0.1 is fine
+        float ambient_strength = 0.1;
+        vec3 ambient_color = radiance * ambient_strength * albedo;
+
+        // Cook-Torrance
+        vec3 light_dir = to_light * inversesqrt(distance_squared);
+        vec3 half_dir = normalize(view_dir + light_dir);
+        float n_dot_l = max(dot(normal, light_dir), 0.0);
+        float n_dot_h = max(dot(normal, half_dir), 0.0);
+
+        vec3 f = fresnel(max(dot(half_dir, view_dir), 0.0), f0);
+        float d = distribution(n_dot_h, roughness);
+        float g = geometry(n_dot_v, n_dot_l, roughness);
+        vec3 specular = d * g * f / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+
+        // Whatever isn't reflected is diffused, except by metals
+        vec3 k_diffuse = (1.0 - f) * (1.0 - metallic);
+        vec3 brdf = k_diffuse * albedo / PI + specular;
+
+        // Lights are measured so a white, matte surface facing them is as bright as their
+        // radiance
+        vec3 reflected = brdf * PI * radiance * n_dot_l * shadow(v_position, lights[i]);
 
-    vec3 result = (ambient_color + diffuse_color) * object_color.xyz;
+        light += ambient_color + reflected;
+    }
 
     // Since lights don't typically (afaik) cast transparency, so we use
     // the alpha here at the end.
-    f_color = vec4(result, object_color.a);
-}
\ No newline at end of file
+    f_color = vec4(light + emissive, object_color.a);
+}
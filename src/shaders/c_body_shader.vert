@@ -5,13 +5,17 @@ layout(location=0) in vec3 a_position;
 layout(location=1) in vec3 a_color;
 layout(location=2) in vec2 a_tex_coords;
 layout(location=3) in vec3 a_normal;
+layout(location=4) in vec3 a_tangent;
 
 layout(location=0) out vec2 v_tex_coords;
 layout(location=1) out vec3 v_normal;
 layout(location=2) out vec3 v_position;
+layout(location=3) out vec3 v_tangent;
+layout(location=4) out vec3 v_view_position;
 
 layout(set=1, binding=0) uniform Camera {
     mat4 u_view_proj;
+    vec4 u_view_position;
 };
 
 layout(set=2, binding=0) uniform Model {
@@ -24,8 +28,12 @@ void main() {
     mat3 normal_matrix = mat3(transpose(inverse(u_model)));
     v_normal = normal_matrix * a_normal;
 
+    // Meshes without texture coordinates have no tangents, these are left at zero
+    v_tangent = mat3(u_model) * a_tangent;
+
     vec4 model_space = u_model * vec4(a_position, 1.0);
     v_position = model_space.xyz;
+    v_view_position = u_view_position.xyz;
 
     gl_Position = u_view_proj * model_space;
-}
\ No newline at end of file
+}
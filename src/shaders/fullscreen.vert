@@ -0,0 +1,12 @@
+// fullscreen.vert
+#version 450
+
+layout(location=0) out vec2 v_tex_coords;
+
+void main() {
+    // A single triangle that covers the whole screen, without a vertex buffer
+    vec2 position = vec2(float((gl_VertexIndex << 1) & 2), float(gl_VertexIndex & 2));
+    v_tex_coords = vec2(position.x, 1.0 - position.y);
+
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
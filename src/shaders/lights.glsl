@@ -0,0 +1,64 @@
+// lights.glsl
+// The lights of the scene and the bodies that shadow them, shared by the shaders of
+// everything that is lit.
+
+// Must match MAX_LIGHTS and MAX_OCCLUDERS in uniform_buffer.rs
+#define MAX_LIGHTS 4
+#define MAX_OCCLUDERS 16
+
+struct PointLight {
+    vec3 position;
+    float intensity;
+    vec3 color;
+    float radius;
+};
+
+struct Occluder {
+    vec3 position;
+    float radius;
+    float bounds;
+};
+
+layout(set=3, binding=0) uniform Lights {
+    PointLight lights[MAX_LIGHTS];
+    Occluder occluders[MAX_OCCLUDERS];
+    uint light_count;
+    uint occluder_count;
+    uint shadows;
+};
+
+// How much of a light reaches a point past the bodies in the way. Each body and the
+// light are discs in the sky, the shadow is the fraction of the light that is covered,
+// so it softens into a penumbra as the discs start to overlap.
+float shadow(vec3 position, PointLight light) {
+    if (shadows == 0u) {
+        return 1.0;
+    }
+
+    vec3 to_light = light.position - position;
+    float light_distance = length(to_light);
+    vec3 light_dir = to_light / light_distance;
+    float light_size = asin(min(light.radius / light_distance, 1.0));
+
+    float visibility = 1.0;
+    for (uint i = 0u; i < occluder_count; i++) {
+        vec3 to_occluder = occluders[i].position - position;
+        float occluder_distance = length(to_occluder);
+
+        // A body doesn't shadow itself, and the star (or anything behind it) can't block it
+        if (occluder_distance < occluders[i].bounds
+            || occluder_distance > light_distance - light.radius) {
+            continue;
+        }
+
+        float occluder_size = asin(min(occluders[i].radius / occluder_distance, 1.0));
+        float separation = acos(clamp(dot(to_occluder / occluder_distance, light_dir), -1.0, 1.0));
+
+        // The most of the light the body can cover, when it is in front of the middle
+        float full = min((occluder_size * occluder_size) / (light_size * light_size), 1.0);
+        float overlap = 1.0 - smoothstep(abs(light_size - occluder_size), light_size + occluder_size, separation);
+        visibility *= 1.0 - full * overlap;
+    }
+
+    return visibility;
+}
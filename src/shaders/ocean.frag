@@ -0,0 +1,54 @@
+// ocean.frag
+#version 450
+
+layout(location=0) in vec3 v_normal;
+layout(location=1) in vec3 v_position;
+layout(location=2) in vec3 v_view_position;
+
+layout(location=0) out vec4 f_color;
+
+#include "lights.glsl"
+
+const vec3 water_color = vec3(0.05, 0.2, 0.4);
+const float shininess = 64.0;
+
+void main() {
+    vec3 normal = normalize(v_normal);
+    vec3 view_dir = normalize(v_view_position - v_position);
+
+    vec3 light = vec3(0.0);
+    vec3 specular_color = vec3(0.0);
+    float specular_alpha = 0.0;
+    for (uint i = 0u; i < light_count; i++) {
+        vec3 to_light = lights[i].position - v_position;
+        float distance_squared = max(dot(to_light, to_light), 0.0001);
+        vec3 light_dir = to_light * inversesqrt(distance_squared);
+
+        // Light falls off with the square of the distance
+        vec3 radiance = lights[i].color * lights[i].intensity / distance_squared;
+
+        vec3 ambient_color = radiance * 0.1;
+
+        // Shadows hide both the diffuse light and the highlight
+        radiance *= shadow(v_position, lights[i]);
+
+        float diffuse_strength = max(dot(normal, light_dir), 0.0);
+        vec3 diffuse_color = radiance * diffuse_strength;
+
+        // Blinn-Phong highlight where the star reflects off the water
+        vec3 half_dir = normalize(view_dir + light_dir);
+        float specular_strength = pow(max(dot(normal, half_dir), 0.0), shininess);
+        specular_strength *= step(0.0, dot(normal, light_dir));
+
+        light += ambient_color + diffuse_color;
+        specular_color += radiance * specular_strength;
+        specular_alpha = max(specular_alpha, specular_strength);
+    }
+
+    // Water is more reflective (and less see-through) at grazing angles
+    float fresnel = pow(1.0 - max(dot(normal, view_dir), 0.0), 5.0);
+    float alpha = mix(0.6, 0.95, fresnel);
+
+    vec3 result = light * water_color + specular_color;
+    f_color = vec4(result, max(alpha, specular_alpha));
+}
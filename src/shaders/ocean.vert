@@ -0,0 +1,31 @@
+// ocean.vert
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec3 a_color;
+layout(location=2) in vec2 a_tex_coords;
+layout(location=3) in vec3 a_normal;
+
+layout(location=0) out vec3 v_normal;
+layout(location=1) out vec3 v_position;
+layout(location=2) out vec3 v_view_position;
+
+layout(set=1, binding=0) uniform Camera {
+    mat4 u_view_proj;
+    vec4 u_view_position;
+};
+
+layout(set=2, binding=0) uniform Model {
+    mat4 u_model;
+};
+
+void main() {
+    mat3 normal_matrix = mat3(transpose(inverse(u_model)));
+    v_normal = normal_matrix * a_normal;
+
+    vec4 model_space = u_model * vec4(a_position, 1.0);
+    v_position = model_space.xyz;
+    v_view_position = u_view_position.xyz;
+
+    gl_Position = u_view_proj * model_space;
+}
@@ -0,0 +1,13 @@
+// skybox.frag
+#version 450
+
+layout(location=0) in vec3 v_direction;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=1, binding=0) uniform textureCube t_skybox;
+layout(set=1, binding=1) uniform sampler s_skybox;
+
+void main() {
+    f_color = texture(samplerCube(t_skybox, s_skybox), v_direction);
+}
@@ -0,0 +1,15 @@
+// starfield.frag
+#version 450
+
+layout(location=0) in vec3 v_color;
+layout(location=1) in vec2 v_tex_coords;
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    // Stars are soft discs that fade towards the edge of their quad
+    float distance = length(v_tex_coords * 2.0 - 1.0);
+    float strength = 1.0 - smoothstep(0.0, 1.0, distance);
+
+    f_color = vec4(v_color * strength, strength);
+}
@@ -0,0 +1,25 @@
+// starfield.vert
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec3 a_color;
+layout(location=2) in vec2 a_tex_coords;
+
+layout(location=0) out vec3 v_color;
+layout(location=1) out vec2 v_tex_coords;
+
+layout(set=0, binding=0) uniform Camera {
+    mat4 u_view_proj;
+    vec4 u_view_position;
+};
+
+void main() {
+    v_color = a_color;
+    v_tex_coords = a_tex_coords;
+
+    // The sky is centred on the camera, so only turning the camera moves it
+    vec4 clip_position = u_view_proj * vec4(u_view_position.xyz + a_position, 1.0);
+
+    // Infinitely far away, behind everything else
+    gl_Position = clip_position.xyww;
+}
@@ -0,0 +1,49 @@
+// tonemap.frag
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_hdr;
+layout(set=0, binding=1) uniform sampler s_hdr;
+
+layout(set=1, binding=0) uniform texture2D t_bloom;
+layout(set=1, binding=1) uniform sampler s_bloom;
+
+layout(set=2, binding=0) uniform PostProcess {
+    float exposure;
+    float bloom_threshold;
+    float bloom_intensity;
+    uint tonemapper;
+};
+
+// Fit of the ACES filmic curve by Krzysztof Narkowicz
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+void main() {
+    vec3 hdr = texture(sampler2D(t_hdr, s_hdr), v_tex_coords).rgb;
+    vec3 bloom = texture(sampler2D(t_bloom, s_bloom), v_tex_coords).rgb;
+
+    vec3 color = (hdr + bloom * bloom_intensity) * exposure;
+
+    // The target is sRGB, so the result is gamma corrected when it is written
+    if (tonemapper == 0u) {
+        color = aces(color);
+    } else {
+        color = reinhard(color);
+    }
+
+    f_color = vec4(color, 1.0);
+}
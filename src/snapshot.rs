@@ -0,0 +1,647 @@
+use crate::atmosphere::AtmosphereConfig;
+use crate::biome::BiomeConfig;
+use crate::c_body::{CBody, CBodyConfig, CBodyGenerator, MeshType, StarConfig, CHUNK_SIZE};
+use crate::generator::{BodyGenerator, GeneratorParameters};
+use crate::mass::Mass;
+use crate::material::{MaterialConfig, MaterialMaps};
+use crate::scenario::{self, ScenarioBody};
+use crate::voxels::{VoxelChunk, VoxelGenerator};
+use anyhow::*;
+use cgmath::{Quaternion, Vector3};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs::{create_dir_all, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Where the GUI saves and loads snapshots, until another path is chosen
+pub const DEFAULT_SNAPSHOT_PATH: &str = "snapshots/snapshot.eris";
+
+/// The first bytes of every snapshot
+const MAGIC: &[u8; 4] = b"ERIS";
+
+/// The fewest bytes a chunk can be stored in, its position and the lengths of its two
+/// grids
+const MIN_CHUNK_BYTES: usize = 5 * 4;
+
+/// Increase this whenever the layout of a snapshot changes, older snapshots can't be
+/// loaded
+pub const SNAPSHOT_VERSION: u32 = 4;
+
+/// Save every body to a file. The terrain of voxel bodies is stored as voxels, so edits
+/// are kept and the bodies don't need to be generated again when they are loaded. Other
+/// bodies can't show edits, so they are generated again from the parameters of their
+/// generator, keeping all of their detail.
+///
+/// Each body has a header with its configuration and the parameters of the generator it
+/// came from, followed by the chunks of voxel bodies. Chunks are run length encoded, then
+/// the chunks of each body are compressed together.
+pub fn save_snapshot(path: &Path, bodies: &[CBody]) -> Result<()> {
+    if let Some(directory) = path.parent() {
+        create_dir_all(directory)?;
+    }
+
+    let bodies: Vec<SavedBody> = bodies
+        .iter()
+        .map(|body| SavedBody {
+            config: body.config(),
+            mass: body.mass,
+            maps: body.material.maps,
+            gen: &body.gen,
+        })
+        .collect();
+    let output = encode(&bodies)?;
+
+    File::create(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?
+        .write_all(&output)?;
+
+    Ok(())
+}
+
+/// Load the bodies saved in a snapshot, ready to be created again
+pub fn load_snapshot(path: &Path) -> Result<Vec<ScenarioBody>> {
+    let mut input: Vec<u8> = Vec::new();
+    File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?
+        .read_to_end(&mut input)?;
+
+    decode(&input).with_context(|| format!("Failed to load {}", path.display()))
+}
+
+/// What is saved of a body
+struct SavedBody<'a> {
+    config: CBodyConfig,
+    mass: f32,
+    maps: MaterialMaps,
+    gen: &'a CBodyGenerator,
+}
+
+fn encode(bodies: &[SavedBody]) -> Result<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::new();
+    output.extend_from_slice(MAGIC);
+    put_u32(&mut output, SNAPSHOT_VERSION);
+    put_u32(&mut output, bodies.len() as u32);
+
+    for body in bodies {
+        write_body(&mut output, body)?;
+    }
+
+    Ok(output)
+}
+
+fn decode(input: &[u8]) -> Result<Vec<ScenarioBody>> {
+    let mut reader = Reader::new(input);
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        bail!("This is not a snapshot");
+    }
+
+    let version = reader.u32()?;
+    if version != SNAPSHOT_VERSION {
+        bail!(
+            "Snapshot version {} is not supported, expected {}",
+            version,
+            SNAPSHOT_VERSION
+        );
+    }
+
+    let count = reader.u32()?;
+    (0..count).map(|_| read_body(&mut reader)).collect()
+}
+
+fn write_body(output: &mut Vec<u8>, body: &SavedBody) -> Result<()> {
+    let SavedBody {
+        config, mass, gen, ..
+    } = body;
+
+    // Header
+    put_string(output, &config.name);
+    put_f32(output, *mass);
+    put_vector(output, config.position);
+    put_vector(output, config.velocity);
+    put_quaternion(output, config.rotation);
+    put_vector(output, config.angular_velocity);
+    put_mesh_type(output, config.mesh_type);
+    put_option(output, config.star, |output, star| {
+        put_vector(output, star.color);
+        put_f32(output, star.luminosity);
+        put_f32(output, star.surface_brightness);
+    });
+    put_option(output, config.sea_level, put_f32);
+    put_option(output, config.atmosphere, |output, atmosphere| {
+        put_f32(output, atmosphere.height);
+        put_vector(output, atmosphere.rayleigh);
+        put_f32(output, atmosphere.rayleigh_scale_height);
+        put_f32(output, atmosphere.mie);
+        put_f32(output, atmosphere.mie_scale_height);
+        put_f32(output, atmosphere.mie_anisotropy);
+    });
+    put_option(output, config.biomes, |output, biomes| {
+        put_u32(output, biomes.seed);
+        put_f32(output, biomes.star_distance);
+    });
+    put_f32(output, config.material.roughness);
+    put_f32(output, config.material.metallic);
+    put_f32(output, config.material.emissive);
+    put_string(output, body.maps.albedo);
+    put_option(output, body.maps.normal, put_string);
+    put_option(output, body.maps.roughness_metallic, put_string);
+    put_option(output, body.maps.emissive, put_string);
+    put_parameters(output, &gen.parameters());
+
+    if config.mesh_type == MeshType::Voxel {
+        write_voxels(output, gen)?;
+    }
+
+    Ok(())
+}
+
+/// Sample the terrain of a body, including any edits, into chunks of voxels
+fn write_voxels(output: &mut Vec<u8>, gen: &CBodyGenerator) -> Result<()> {
+    put_f32(output, gen.surface_radius());
+    put_f32(output, gen.bounds());
+    put_f32(output, gen.detail_angle());
+    put_u32(output, CHUNK_SIZE as u32);
+
+    // Chunks
+    let mut chunks: Vec<u8> = Vec::new();
+    let (min, max) = gen.chunk_range();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                let position = Vector3::new(x, y, z);
+                put_i32(&mut chunks, x);
+                put_i32(&mut chunks, y);
+                put_i32(&mut chunks, z);
+                VoxelChunk::sample(gen, position).encode(&mut chunks);
+            }
+        }
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&chunks)?;
+    let compressed = encoder.finish()?;
+
+    put_u32(
+        output,
+        ((max.x - min.x + 1) * (max.y - min.y + 1) * (max.z - min.z + 1)) as u32,
+    );
+    put_u32(output, compressed.len() as u32);
+    output.extend_from_slice(&compressed);
+
+    Ok(())
+}
+
+fn read_body(reader: &mut Reader) -> Result<ScenarioBody> {
+    // Header
+    let name = reader.string()?;
+    let mass = reader.f32()?;
+    let position = reader.vector()?;
+    let velocity = reader.vector()?;
+    let rotation = reader.quaternion()?;
+    let angular_velocity = reader.vector()?;
+    let mesh_type = reader.mesh_type()?;
+    let star = reader.option(|r| {
+        Ok(StarConfig {
+            color: r.vector()?,
+            luminosity: r.f32()?,
+            surface_brightness: r.f32()?,
+        })
+    })?;
+    let sea_level = reader.option(|r| r.f32())?;
+    let atmosphere = reader.option(|r| {
+        Ok(AtmosphereConfig {
+            height: r.f32()?,
+            rayleigh: r.vector()?,
+            rayleigh_scale_height: r.f32()?,
+            mie: r.f32()?,
+            mie_scale_height: r.f32()?,
+            mie_anisotropy: r.f32()?,
+        })
+    })?;
+    let biomes = reader.option(|r| {
+        Ok(BiomeConfig {
+            seed: r.u32()?,
+            star_distance: r.f32()?,
+        })
+    })?;
+    let material = MaterialConfig {
+        roughness: reader.f32()?,
+        metallic: reader.f32()?,
+        emissive: reader.f32()?,
+    };
+    let maps = MaterialMaps {
+        albedo: reader.texture()?,
+        normal: reader.option(|r| r.texture())?,
+        roughness_metallic: reader.option(|r| r.texture())?,
+        emissive: reader.option(|r| r.texture())?,
+    };
+    let parameters = reader.parameters()?;
+
+    let generator: Box<dyn BodyGenerator> = match mesh_type {
+        MeshType::Voxel => Box::new(read_voxels(reader, &name, parameters)?),
+        _ => parameters
+            .build()
+            .with_context(|| format!("Failed to generate '{}' again", name))?,
+    };
+
+    Ok(ScenarioBody {
+        config: CBodyConfig {
+            name,
+            mass: Mass::Fixed(mass),
+            position,
+            velocity,
+            rotation,
+            angular_velocity,
+            mesh_type,
+            star,
+            sea_level,
+            atmosphere,
+            biomes,
+            material,
+        },
+        generator,
+        maps,
+    })
+}
+
+/// The terrain of a voxel body, sampled from a generator with these parameters
+fn read_voxels(
+    reader: &mut Reader,
+    name: &str,
+    parameters: GeneratorParameters,
+) -> Result<VoxelGenerator> {
+    let surface_radius = reader.f32()?;
+    let bounds = reader.f32()?;
+    let detail_angle = reader.f32()?;
+
+    let chunk_size = reader.u32()?;
+    if chunk_size != CHUNK_SIZE as u32 {
+        bail!(
+            "'{}' was saved with chunks of {} voxels, expected {}",
+            name,
+            chunk_size,
+            CHUNK_SIZE
+        );
+    }
+
+    // Chunks
+    let count = reader.u32()?;
+    let length = reader.u32()? as usize;
+    let mut chunk_data: Vec<u8> = Vec::new();
+    ZlibDecoder::new(reader.bytes(length)?)
+        .read_to_end(&mut chunk_data)
+        .with_context(|| format!("Failed to decompress the chunks of '{}'", name))?;
+
+    // Every chunk takes at least its position and the lengths of its grids, so a count
+    // larger than the data can hold is never allocated for
+    let mut chunk_reader = Reader::new(&chunk_data);
+    let mut chunks: Vec<VoxelChunk> =
+        Vec::with_capacity((count as usize).min(chunk_data.len() / MIN_CHUNK_BYTES));
+    for _ in 0..count {
+        let position = Vector3::new(
+            chunk_reader.i32()?,
+            chunk_reader.i32()?,
+            chunk_reader.i32()?,
+        );
+        let (chunk, read) = VoxelChunk::decode(position, chunk_reader.remaining())?;
+        chunk_reader.skip(read);
+        chunks.push(chunk);
+    }
+
+    Ok(VoxelGenerator::new(
+        parameters,
+        surface_radius,
+        bounds,
+        detail_angle,
+        chunks,
+    ))
+}
+
+fn put_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_i32(output: &mut Vec<u8>, value: i32) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_f32(output: &mut Vec<u8>, value: f32) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_vector(output: &mut Vec<u8>, value: Vector3<f32>) {
+    put_f32(output, value.x);
+    put_f32(output, value.y);
+    put_f32(output, value.z);
+}
+
+fn put_quaternion(output: &mut Vec<u8>, value: Quaternion<f32>) {
+    put_f32(output, value.s);
+    put_vector(output, value.v);
+}
+
+fn put_string(output: &mut Vec<u8>, value: &str) {
+    put_u32(output, value.len() as u32);
+    output.extend_from_slice(value.as_bytes());
+}
+
+fn put_f64(output: &mut Vec<u8>, value: f64) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_parameters(output: &mut Vec<u8>, parameters: &GeneratorParameters) {
+    put_string(output, &parameters.kind);
+    put_u32(output, parameters.values.len() as u32);
+    for (name, value) in &parameters.values {
+        put_string(output, name);
+        put_f64(output, *value);
+    }
+    put_u32(output, parameters.labels.len() as u32);
+    for (name, label) in &parameters.labels {
+        put_string(output, name);
+        put_string(output, label);
+    }
+}
+
+fn put_option<T, F: Fn(&mut Vec<u8>, T)>(output: &mut Vec<u8>, value: Option<T>, put: F) {
+    match value {
+        Some(value) => {
+            output.push(1);
+            put(output, value);
+        }
+        None => output.push(0),
+    }
+}
+
+fn put_mesh_type(output: &mut Vec<u8>, mesh_type: MeshType) {
+    let (tag, value) = match mesh_type {
+        MeshType::Voxel => (0, 0),
+        MeshType::Icosphere { subdivisions } => (1, subdivisions),
+        MeshType::CubeSphere { subdivisions } => (2, subdivisions),
+        MeshType::Quadtree { max_depth } => (3, max_depth),
+    };
+
+    output.push(tag);
+    put_u32(output, value);
+}
+
+/// Reads values from the bytes of a snapshot, in the order they were written
+struct Reader<'a> {
+    input: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Self { input, offset: 0 }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .input
+            .get(self.offset..self.offset + length)
+            .context("Snapshot ended unexpectedly")?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        &self.input[self.offset..]
+    }
+
+    fn skip(&mut self, length: usize) {
+        self.offset = (self.offset + length).min(self.input.len());
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let b = self.bytes(8)?;
+        Ok(f64::from_le_bytes([
+            b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+        ]))
+    }
+
+    fn vector(&mut self) -> Result<Vector3<f32>> {
+        Ok(Vector3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn quaternion(&mut self) -> Result<Quaternion<f32>> {
+        Ok(Quaternion::from_sv(self.f32()?, self.vector()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.u32()? as usize;
+        Ok(String::from_utf8(self.bytes(length)?.to_vec())?)
+    }
+
+    /// The label of one of the images bodies can be drawn with
+    fn texture(&mut self) -> Result<&'static str> {
+        let (label, _) = scenario::find_texture(&self.string()?)?;
+        Ok(label)
+    }
+
+    fn parameters(&mut self) -> Result<GeneratorParameters> {
+        let kind = self.string()?;
+        let count = self.u32()?;
+        let values = (0..count)
+            .map(|_| Ok((self.string()?, self.f64()?)))
+            .collect::<Result<_>>()?;
+        let count = self.u32()?;
+        let labels = (0..count)
+            .map(|_| Ok((self.string()?, self.string()?)))
+            .collect::<Result<_>>()?;
+
+        Ok(GeneratorParameters {
+            kind,
+            values,
+            labels,
+        })
+    }
+
+    fn option<T, F: Fn(&mut Self) -> Result<T>>(&mut self, read: F) -> Result<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => read(self).map(Some),
+        }
+    }
+
+    fn mesh_type(&mut self) -> Result<MeshType> {
+        let tag = self.u8()?;
+        let value = self.u32()?;
+
+        match tag {
+            0 => Ok(MeshType::Voxel),
+            1 => Ok(MeshType::Icosphere {
+                subdivisions: value,
+            }),
+            2 => Ok(MeshType::CubeSphere {
+                subdivisions: value,
+            }),
+            3 => Ok(MeshType::Quadtree { max_depth: value }),
+            _ => bail!("Unknown mesh type {}", tag),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{HeightmapGenerator, SphereGenerator};
+
+    fn test_config() -> CBodyConfig {
+        CBodyConfig {
+            name: "Test Body".to_string(),
+            mass: Mass::Density(1.0),
+            position: Vector3::new(10.0, -2.0, 3.5),
+            velocity: Vector3::new(0.0, 0.25, 0.0),
+            rotation: Quaternion::new(0.5, 0.5, 0.5, 0.5),
+            angular_velocity: Vector3::new(0.0, 0.01, 0.0),
+            mesh_type: MeshType::Voxel,
+            star: Some(StarConfig {
+                color: Vector3::new(1.0, 0.5, 0.25),
+                luminosity: 100.0,
+                surface_brightness: 2.0,
+            }),
+            sea_level: Some(1.5),
+            atmosphere: None,
+            biomes: None,
+            material: MaterialConfig {
+                roughness: 0.5,
+                metallic: 0.25,
+                emissive: 2.0,
+            },
+        }
+    }
+
+    const TEST_MAPS: MaterialMaps = MaterialMaps {
+        albedo: "moon1024.bmp",
+        normal: Some("moon1024_normal.png"),
+        roughness_metallic: None,
+        emissive: Some("sun.png"),
+    };
+
+    fn encode_test_body() -> (CBodyConfig, CBodyGenerator, Vec<u8>) {
+        let config = test_config();
+        let gen = CBodyGenerator::new(Box::new(SphereGenerator::new(4.0)), &config);
+        let bytes = encode(&[SavedBody {
+            config: config.clone(),
+            mass: 12.5,
+            maps: TEST_MAPS,
+            gen: &gen,
+        }])
+        .unwrap();
+
+        (config, gen, bytes)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (config, gen, bytes) = encode_test_body();
+        let loaded = decode(&bytes).unwrap();
+        assert_eq!(loaded.len(), 1);
+
+        let body = &loaded[0];
+        assert_eq!(body.config.name, config.name);
+        assert_eq!(body.config.mass, Mass::Fixed(12.5));
+        assert_eq!(body.config.position, config.position);
+        assert_eq!(body.config.velocity, config.velocity);
+        assert_eq!(body.config.rotation, config.rotation);
+        assert_eq!(body.config.angular_velocity, config.angular_velocity);
+        assert_eq!(body.config.mesh_type, config.mesh_type);
+        assert_eq!(
+            body.config
+                .star
+                .map(|s| (s.color, s.luminosity, s.surface_brightness)),
+            Some((Vector3::new(1.0, 0.5, 0.25), 100.0, 2.0))
+        );
+        assert_eq!(body.config.sea_level, config.sea_level);
+        assert_eq!(body.config.material, config.material);
+        assert_eq!(body.maps, TEST_MAPS);
+
+        // The terrain comes back as voxels, which still remember the generator
+        assert_eq!(body.generator.parameters(), gen.parameters());
+        assert_eq!(body.generator.surface_radius(), gen.surface_radius());
+        assert!(body.generator.density(Vector3::new(0.0, 0.0, 0.0)) > 0.0);
+        assert!(
+            body.generator
+                .density(Vector3::new(0.0, gen.bounds() + 1.0, 0.0))
+                < 0.0
+        );
+    }
+
+    #[test]
+    fn other_bodies_are_generated_again() {
+        let config = CBodyConfig {
+            mesh_type: MeshType::Quadtree { max_depth: 6 },
+            ..test_config()
+        };
+        let gen = CBodyGenerator::new(
+            Box::new(HeightmapGenerator::load(16.0, "moon1024.bmp", 1.0).unwrap()),
+            &config,
+        );
+        let bytes = encode(&[SavedBody {
+            config: config.clone(),
+            mass: 12.5,
+            maps: TEST_MAPS,
+            gen: &gen,
+        }])
+        .unwrap();
+
+        let loaded = decode(&bytes).unwrap();
+        let body = &loaded[0];
+        assert_eq!(body.config.mesh_type, config.mesh_type);
+        assert_eq!(body.generator.parameters(), gen.parameters());
+
+        // The heightmap is loaded again, so none of the detail is lost
+        for direction in [Vector3::unit_x(), Vector3::new(0.6, 0.0, -0.8)].iter() {
+            assert_eq!(
+                body.generator.surface_height(*direction),
+                gen.base_height(*direction)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_snapshot_is_an_error() {
+        let (_, _, bytes) = encode_test_body();
+        for length in [0, 4, 8, 12, bytes.len() / 2, bytes.len() - 1].iter() {
+            assert!(decode(&bytes[..*length]).is_err());
+        }
+    }
+
+    #[test]
+    fn chunk_count_is_bounded_by_the_data() {
+        let (_, _, mut bytes) = encode_test_body();
+
+        // The chunk count comes just before the length of the compressed chunks, which
+        // run to the end of the snapshot
+        let length_offset = (0..bytes.len() - 4)
+            .rev()
+            .find(|&i| {
+                let b = &bytes[i..i + 4];
+                u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize == bytes.len() - i - 4
+            })
+            .unwrap();
+        bytes[length_offset - 4..length_offset].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::utils::Vertex;
+use cgmath::num_traits::FloatConst;
+use cgmath::{InnerSpace, Vector2, Vector3};
+use std::collections::HashMap;
+
+/// The six faces of a cube as (normal, a, b). The face axes are ordered so that
+/// `a x b = normal`, which keeps triangles built from them counter-clockwise.
+pub const CUBE_FACES: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+    ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+];
+
+/// Map a point on the surface of a unit cube onto the unit sphere. This spreads
+/// the points more evenly than normalizing.
+pub fn cube_to_sphere(p: Vector3<f32>) -> Vector3<f32> {
+    let (x2, y2, z2) = (p.x * p.x, p.y * p.y, p.z * p.z);
+
+    Vector3::new(
+        p.x * (1.0 - y2 / 2.0 - z2 / 2.0 + y2 * z2 / 3.0).sqrt(),
+        p.y * (1.0 - z2 / 2.0 - x2 / 2.0 + z2 * x2 / 3.0).sqrt(),
+        p.z * (1.0 - x2 / 2.0 - y2 / 2.0 + x2 * y2 / 3.0).sqrt(),
+    )
+}
+
+/// The point on a face of the cube at (s, t), where both range from -1 to 1
+pub fn cube_face_point(face: usize, s: f32, t: f32) -> Vector3<f32> {
+    let (normal, a, b) = CUBE_FACES[face];
+    Vector3::from(normal) + Vector3::from(a) * s + Vector3::from(b) * t
+}
+
+/// Equirectangular texture coordinates for a direction from the centre of a sphere
+pub fn equirectangular_uv(normal: Vector3<f32>) -> Vector2<f32> {
+    Vector2::new(
+        0.5 + normal.z.atan2(normal.x) / (2.0 * f32::PI()),
+        0.5 - normal.y.asin() / f32::PI(),
+    )
+}
+
+/// The direction from the centre of a sphere to a point on an equirectangular map, the
+/// inverse of `equirectangular_uv`
+pub fn equirectangular_direction(uv: Vector2<f32>) -> Vector3<f32> {
+    let longitude = (uv.x - 0.5) * 2.0 * f32::PI();
+    let latitude = (0.5 - uv.y) * f32::PI();
+
+    Vector3::new(
+        latitude.cos() * longitude.cos(),
+        latitude.sin(),
+        latitude.cos() * longitude.sin(),
+    )
+}
+
+/// The tangent of a sphere at a direction, points along increasing u
+pub fn sphere_tangent(normal: Vector3<f32>) -> Vector3<f32> {
+    let tangent = Vector3::new(-normal.z, 0.0, normal.x);
+
+    // The tangent is undefined at the poles
+    if tangent.magnitude2() < 1e-8 {
+        return Vector3::new(1.0, 0.0, 0.0);
+    }
+
+    tangent.normalize()
+}
+
+/// Build a sphere by repeatedly subdividing an icosahedron
+pub fn build_icosphere(radius: f32, subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+
+    let mut points: Vec<Vector3<f32>> = vec![
+        Vector3::new(-1.0, t, 0.0),
+        Vector3::new(1.0, t, 0.0),
+        Vector3::new(-1.0, -t, 0.0),
+        Vector3::new(1.0, -t, 0.0),
+        Vector3::new(0.0, -1.0, t),
+        Vector3::new(0.0, 1.0, t),
+        Vector3::new(0.0, -1.0, -t),
+        Vector3::new(0.0, 1.0, -t),
+        Vector3::new(t, 0.0, -1.0),
+        Vector3::new(t, 0.0, 1.0),
+        Vector3::new(-t, 0.0, -1.0),
+        Vector3::new(-t, 0.0, 1.0),
+    ]
+    .into_iter()
+    .map(|p| p.normalize())
+    .collect();
+
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges are shared between triangles, so cache the midpoints
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, points: &mut Vec<Vector3<f32>>| -> u32 {
+            let key = if a < b { (a, b) } else { (b, a) };
+            *midpoints.entry(key).or_insert_with(|| {
+                points.push(((points[a as usize] + points[b as usize]) / 2.0).normalize());
+                points.len() as u32 - 1
+            })
+        };
+
+        let mut subdivided = Vec::with_capacity(triangles.len() * 4);
+        for [a, b, c] in triangles {
+            let ab = midpoint(a, b, &mut points);
+            let bc = midpoint(b, c, &mut points);
+            let ca = midpoint(c, a, &mut points);
+
+            subdivided.push([a, ab, ca]);
+            subdivided.push([b, bc, ab]);
+            subdivided.push([c, ca, bc]);
+            subdivided.push([ab, bc, ca]);
+        }
+
+        triangles = subdivided;
+    }
+
+    build_sphere_vertices(radius, &points, &triangles)
+}
+
+/// Build a sphere by projecting a subdivided cube onto it
+pub fn build_cube_sphere(radius: f32, subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let resolution = subdivisions.max(1);
+
+    let mut points: Vec<Vector3<f32>> = Vec::new();
+    let mut triangles: Vec<[u32; 3]> = Vec::new();
+
+    for face in 0..CUBE_FACES.len() {
+        let start = points.len() as u32;
+
+        for i in 0..=resolution {
+            for j in 0..=resolution {
+                let s = 2.0 * i as f32 / resolution as f32 - 1.0;
+                let t = 2.0 * j as f32 / resolution as f32 - 1.0;
+
+                points.push(cube_to_sphere(cube_face_point(face, s, t)));
+            }
+        }
+
+        let index = |i: u32, j: u32| start + i * (resolution + 1) + j;
+        for i in 0..resolution {
+            for j in 0..resolution {
+                triangles.push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+                triangles.push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+            }
+        }
+    }
+
+    build_sphere_vertices(radius, &points, &triangles)
+}
+
+/// Build the vertices for a set of triangles on the unit sphere
+fn build_sphere_vertices(
+    radius: f32,
+    points: &[Vector3<f32>],
+    triangles: &[[u32; 3]],
+) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices: Vec<Vertex> = points
+        .iter()
+        .map(|p| Vertex::with_tangent(*p * radius, *p, equirectangular_uv(*p), sphere_tangent(*p)))
+        .collect();
+
+    let indices = wrap_seams(&mut vertices, triangles);
+
+    (vertices, indices)
+}
+
+/// Build the indices for a set of triangles with equirectangular texture coordinates. Vertices
+/// are duplicated where a triangle crosses the texture seam or touches a pole, so the texture
+/// coordinates don't stretch across the whole texture.
+pub fn wrap_seams(vertices: &mut Vec<Vertex>, triangles: &[[u32; 3]]) -> Vec<u32> {
+    let mut indices: Vec<u32> = Vec::with_capacity(triangles.len() * 3);
+
+    // Seam vertices can be shared by any triangle that needs them
+    let mut wrapped: HashMap<u32, u32> = HashMap::new();
+
+    for triangle in triangles {
+        let mut triangle = *triangle;
+        let min_u = triangle
+            .iter()
+            .map(|i| vertices[*i as usize].tex_coord.x)
+            .fold(f32::MAX, f32::min);
+        let max_u = triangle
+            .iter()
+            .map(|i| vertices[*i as usize].tex_coord.x)
+            .fold(f32::MIN, f32::max);
+
+        // Crossing the seam, move the vertices on the left over to the right
+        if max_u - min_u > 0.5 {
+            for index in triangle.iter_mut() {
+                if vertices[*index as usize].tex_coord.x < 0.5 {
+                    let original = *index;
+                    *index = *wrapped.entry(original).or_insert_with(|| {
+                        let mut vertex = vertices[original as usize];
+                        vertex.tex_coord.x += 1.0;
+                        vertices.push(vertex);
+                        vertices.len() as u32 - 1
+                    });
+                }
+            }
+        }
+
+        // A pole has no single u, so use the average of the rest of the triangle
+        for k in 0..3 {
+            let vertex = vertices[triangle[k] as usize];
+            if vertex.position.normalize().y.abs() > 0.9999 {
+                let u = (vertices[triangle[(k + 1) % 3] as usize].tex_coord.x
+                    + vertices[triangle[(k + 2) % 3] as usize].tex_coord.x)
+                    / 2.0;
+
+                let mut pole = vertex;
+                pole.tex_coord.x = u;
+                vertices.push(pole);
+                triangle[k] = vertices.len() as u32 - 1;
+            }
+        }
+
+        indices.extend_from_slice(&triangle);
+    }
+
+    indices
+}
@@ -1,14 +1,34 @@
 use winit::{event::*, window::Window};
 
-use crate::c_body::CBody;
-use crate::mesh::DrawMesh;
-use crate::texture::Texture;
-use crate::{camera, render_pipeline, texture, uniform_buffer};
+use crate::biome::Biome;
+use crate::c_body::{CBody, MeshOptions, MeshType, Mesher};
+use crate::export::{self, ExportFormat};
+use crate::material::Material;
+use crate::normals::NormalMode;
+use crate::post_process::Tonemapper;
+use crate::renderer::{self, Renderer};
+use crate::scenario::ScenarioBody;
+use crate::terrain::{TerrainEdit, TerrainTool};
+use crate::texture::{SamplerConfig, TextureFilter};
+use crate::worker::WorkerPool;
+use crate::{camera, scenario, snapshot, utils};
 use cgmath::num_traits::FloatConst;
-use cgmath::{InnerSpace, Rotation3, Vector3};
+use cgmath::{InnerSpace, Vector3, Zero};
 use imgui::FontSource;
+use std::cmp::Ordering;
+use std::path::Path;
 use std::time::Duration;
 
+/// The size of the biome map shown in the GUI
+const BIOME_MAP_WIDTH: u32 = 256;
+const BIOME_MAP_HEIGHT: u32 = 128;
+
+/// A map of the biomes of a body, shown in its own window
+struct BiomeMap {
+    body_name: String,
+    texture_id: imgui::TextureId,
+}
+
 pub struct State {
     pub surface: wgpu::Surface,
     pub device: wgpu::Device,
@@ -16,17 +36,21 @@ pub struct State {
     pub sc_desc: wgpu::SwapChainDescriptor,
     pub swap_chain: wgpu::SwapChain,
     pub size: winit::dpi::PhysicalSize<u32>,
-    pub render_pipeline: wgpu::RenderPipeline,
-    c_body_pipeline: wgpu::RenderPipeline,
-    depth_texture: texture::Texture,
+    renderer: Renderer,
     camera: camera::Camera,
     camera_controller: camera::CameraController,
     bodies: Vec<CBody>,
+    worker_pool: WorkerPool,
     pub(crate) gui_context: imgui::Context,
     pub(crate) gui_platform: imgui_winit_support::WinitPlatform,
     gui_renderer: imgui_wgpu::Renderer,
     mouse_pressed: bool,
-    lights: uniform_buffer::UniformBuffer<uniform_buffer::LightUniform>,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
+    terrain_tool: TerrainTool,
+    brush_radius: f32,
+    biome_map: Option<BiomeMap>,
+    /// Where snapshots are saved to and loaded from
+    snapshot_path: imgui::ImString,
 }
 
 impl State {
@@ -82,108 +106,24 @@ impl State {
 
         let camera_controller = camera::CameraController::new(32.0, 0.2);
 
-        // Pipeline layout
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[
-                    &Texture::create_bind_group_layout(&device),
-                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
-                        wgpu::ShaderStage::VERTEX,
-                        &device,
-                    ),
-                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
-                        wgpu::ShaderStage::VERTEX,
-                        &device,
-                    ),
-                    &uniform_buffer::UniformBufferUtils::create_bind_group_layout(
-                        wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                        &device,
-                    ),
-                ],
-                push_constant_ranges: &[],
-            });
-
-        let render_pipeline =
-            render_pipeline::RenderPipelineBuilder::new(sc_desc.format, "Main Pipeline")
-                .with_vertex_shader(wgpu::include_spirv!("shaders/shader.vert.spv"))
-                .with_fragment_shader(wgpu::include_spirv!("shaders/shader.frag.spv"))
-                .with_layout(&render_pipeline_layout)
-                .build(&device)
-                .unwrap();
-
-        let c_body_pipeline =
-            render_pipeline::RenderPipelineBuilder::new(sc_desc.format, "C Body Pipeline")
-                .with_vertex_shader(wgpu::include_spirv!("shaders/c_body_shader.vert.spv"))
-                .with_fragment_shader(wgpu::include_spirv!("shaders/c_body_shader.frag.spv"))
-                .with_layout(&render_pipeline_layout)
-                //.with_topology(wgpu::PrimitiveTopology::LineList)
-                .build(&device)
-                .unwrap();
-
-        let depth_texture =
-            texture::Texture::create_depth_texture(&device, &sc_desc, "depth_texture");
-
-        let mut bodies = Vec::new();
-
-        let sun_texture = texture::Texture::from_bytes(
+        let renderer = Renderer::new(
             &device,
             &queue,
-            include_bytes!("images/sun.png"),
-            "sun.png",
-        )
-        .unwrap();
-
-        let sun = CBody::new(
-            "Main Star".to_string(),
-            1000000.0,
-            32.0,
-            cgmath::Vector3::new(0.0, 0.0, 0.0),
-            cgmath::Vector3::new(0.0, 0.0, 0.0),
-            sun_texture,
-            &device,
+            &sc_desc,
+            renderer::supported_sample_counts(&adapter),
         );
 
-        let inner_texture = texture::Texture::from_bytes(
-            &device,
-            &queue,
-            include_bytes!("images/earth.png"),
-            "earth.png",
-        )
-        .unwrap();
+        // Bodies are generated in the background
+        let worker_pool = WorkerPool::new();
 
-        let outer_texture = texture::Texture::from_bytes(
+        let bodies = Self::create_bodies(
+            scenario::default_scenario(),
             &device,
             &queue,
-            include_bytes!("images/earth.png"),
-            "earth.png",
-        )
-        .unwrap();
-
-        let planet = CBody::new(
-            "Planet".to_string(),
-            10000.0,
-            12.0,
-            cgmath::Vector3::new(200.0, 0.0, 0.0),
-            cgmath::Vector3::new(0.0, 0.0, -sun.calculate_velocity_at_radius(200.0)),
-            inner_texture,
-            &device,
+            &worker_pool,
+            renderer.sampler,
         );
 
-        let moon = CBody::new(
-            "Moon".to_string(),
-            0.1,
-            2.0,
-            cgmath::Vector3::new(200.0 + 12.0, 0.0, 0.0),
-            cgmath::Vector3::new(0.0, 0.0, -planet.calculate_velocity_at_radius(12.0)),
-            outer_texture,
-            &device,
-        );
-
-        bodies.push(sun);
-        bodies.push(planet);
-        bodies.push(moon);
-
         // -------------- GUI ------------------ //
 
         // Setup ImGUI and attach it to our window, ImGui is used as the GUI for this
@@ -218,13 +158,6 @@ impl State {
         let gui_renderer =
             imgui_wgpu::Renderer::new(&mut gui_context, &device, &queue, renderer_config);
 
-        let lights = uniform_buffer::UniformBuffer::new(
-            "Light Uniform Buffer",
-            wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-            uniform_buffer::LightUniform::new((2.0, 2.0, 2.0).into(), (1.0, 1.0, 1.0).into()),
-            &device,
-        );
-
         Self {
             surface,
             device,
@@ -232,17 +165,20 @@ impl State {
             sc_desc,
             swap_chain,
             size,
-            render_pipeline,
-            c_body_pipeline,
-            depth_texture,
+            renderer,
             camera,
             camera_controller,
             bodies,
+            worker_pool,
             gui_context,
             gui_platform,
             gui_renderer,
             mouse_pressed: false,
-            lights,
+            cursor_position: (0.0, 0.0).into(),
+            terrain_tool: TerrainTool::None,
+            brush_radius: 2.0,
+            biome_map: None,
+            snapshot_path: imgui::ImString::new(snapshot::DEFAULT_SNAPSHOT_PATH),
         }
     }
 
@@ -253,8 +189,7 @@ impl State {
         self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
 
         // After the swapchain is recreated, we need to rebuild the depth texture
-        self.depth_texture =
-            texture::Texture::create_depth_texture(&self.device, &self.sc_desc, "depth_texture");
+        self.renderer.resize(&self.device, &self.sc_desc);
 
         // The screen projection needs to be updated
         self.camera
@@ -282,7 +217,133 @@ impl State {
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera_controller.process_keyboard(event)
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = *position;
+                false
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                // Don't edit terrain when clicking on the GUI
+                if self.terrain_tool == TerrainTool::None
+                    || self.gui_context.io().want_capture_mouse
+                {
+                    return false;
+                }
+
+                self.apply_terrain_tool();
+                true
+            }
+            _ => self.camera_controller.process_keyboard(event),
+        }
+    }
+
+    /// Carve or add terrain on the surface of the body under the cursor
+    fn apply_terrain_tool(&mut self) {
+        let origin = self.camera.position;
+        let direction = self.camera.screen_ray(
+            self.cursor_position.x as f32,
+            self.cursor_position.y as f32,
+            self.size.width as f32,
+            self.size.height as f32,
+        );
+
+        // Find the closest body that was hit
+        let hit = self
+            .bodies
+            .iter_mut()
+            .filter_map(|b| b.raycast(origin, direction).map(|d| (b, d)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+
+        if let Some((body, distance)) = hit {
+            let center = body.to_local(origin + direction * distance);
+            let radius = self.brush_radius;
+
+            let edit = match self.terrain_tool {
+                TerrainTool::Carve => TerrainEdit::Carve { center, radius },
+                TerrainTool::Add => TerrainEdit::Add { center, radius },
+                TerrainTool::None => return,
+            };
+
+            body.apply_edit(edit, &self.worker_pool);
+        }
+    }
+
+    /// Create the bodies of a scenario, their terrain is built in the background
+    pub(crate) fn create_bodies(
+        scenario: Vec<ScenarioBody>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &WorkerPool,
+        sampler: SamplerConfig,
+    ) -> Vec<CBody> {
+        scenario
+            .into_iter()
+            .map(|body| {
+                let material =
+                    Material::new(device, queue, body.maps, body.config.material, sampler).unwrap();
+
+                CBody::new(body.config, body.generator, material, pool, device)
+            })
+            .collect()
+    }
+
+    /// Draw the biomes of a body into a texture that can be shown in the GUI
+    fn create_biome_map(
+        body: &CBody,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut imgui_wgpu::Renderer,
+    ) -> Option<BiomeMap> {
+        let pixels = body.gen.biome_map(BIOME_MAP_WIDTH, BIOME_MAP_HEIGHT)?;
+
+        let texture = imgui_wgpu::Texture::new(
+            device,
+            renderer,
+            imgui_wgpu::TextureConfig {
+                size: wgpu::Extent3d {
+                    width: BIOME_MAP_WIDTH,
+                    height: BIOME_MAP_HEIGHT,
+                    depth: 1,
+                },
+                label: Some("Biome Map"),
+                format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+                ..Default::default()
+            },
+        );
+        texture.write(queue, &pixels, BIOME_MAP_WIDTH, BIOME_MAP_HEIGHT);
+
+        Some(BiomeMap {
+            body_name: body.name.clone(),
+            texture_id: renderer.textures.insert(texture),
+        })
+    }
+
+    /// When two bodies collide, the smaller body is destroyed and leaves a crater on
+    /// the larger body
+    fn process_impacts(&mut self) {
+        let mut i = 0;
+        while i < self.bodies.len() {
+            let body = &self.bodies[i];
+            let target = self.bodies.iter().enumerate().position(|(j, other)| {
+                let distance = (other.position - body.position).magnitude();
+                j != i
+                    && body.mass <= other.mass
+                    && distance < body.gen.surface_radius() + other.gen.surface_radius()
+            });
+
+            match target {
+                Some(j) => {
+                    let impactor = self.bodies.remove(i);
+                    let j = if j > i { j - 1 } else { j };
+                    self.bodies[j].absorb_impact(&impactor, &self.worker_pool);
+                }
+                None => i += 1,
+            }
+        }
     }
 
     pub fn update(&mut self, dt: Duration) {
@@ -297,6 +358,7 @@ impl State {
             // Calculate net force against other bodies
 
             // This loop iterates over all bodies that are no the current body
+            let mut torque: Vector3<f32> = Vector3::zero();
             for body2 in before.iter().chain(after.iter()) {
                 let sqr_distance: f32 = (body2.position - body.position).magnitude2();
                 let force_direction: Vector3<f32> = (body2.position - body.position).normalize();
@@ -306,10 +368,13 @@ impl State {
                 let acceleration: Vector3<f32> = force / body.mass;
 
                 body.velocity += acceleration;
+
+                // Elongated bodies are turned by the tides of the bodies around them
+                torque += body.gravity_gradient_torque(body2);
             }
 
             // Run simulations
-            body.update(dt);
+            body.update(torque, dt);
 
             self.queue.write_buffer(
                 &body.uniform_buffer.buffer,
@@ -318,21 +383,24 @@ impl State {
             );
         }
 
+        self.process_impacts();
+
         // Update camera positions
         self.camera_controller.update_camera(&mut self.camera, dt);
         self.camera.update_uniforms(&self.queue);
 
-        // TEMP, THIS IS TEMP
-        // Used to test how lighting is working
-        let old_position: cgmath::Vector3<_> = self.lights.data.position.into();
-        self.lights.data.position =
-            cgmath::Quaternion::from_axis_angle((0.0, 1.0, 0.0).into(), cgmath::Deg(1.0))
-                * old_position;
-        self.queue.write_buffer(
-            &self.lights.buffer,
-            0,
-            bytemuck::cast_slice(&[self.lights.data]),
-        );
+        // Upload generated meshes, and refine the surface of bodies around the camera
+        for body in self.bodies.iter_mut() {
+            body.update_meshes(
+                &self.camera,
+                self.size.height,
+                &self.worker_pool,
+                &self.device,
+            );
+        }
+
+        // The stars light the other bodies from wherever they are now
+        self.renderer.update_lights(&self.bodies, &self.queue);
     }
 
     pub fn render(&mut self, window: &Window) -> Result<(), wgpu::SwapChainError> {
@@ -342,19 +410,60 @@ impl State {
             .expect("Failed to prepare frame!");
 
         let ui = self.gui_context.frame();
+        let mut export_request: Option<(usize, ExportFormat)> = None;
+        let mut mesh_request: Option<(usize, MeshOptions)> = None;
+        let mut biome_map_request: Option<usize> = None;
+        let mut close_biome_map = false;
+        let mut save_snapshot = false;
+        let mut load_snapshot = false;
+        let mut graphics = self.renderer.post_process.settings();
+        let mut sampler = self.renderer.sampler;
+        let mut sample_count = self.renderer.sample_count();
+        let sample_counts = self.renderer.sample_counts().to_vec();
         {
             let ui_bodies = self.bodies.iter();
+            let export_request = &mut export_request;
+            let mesh_request = &mut mesh_request;
+            let biome_map_request = &mut biome_map_request;
             let cam = &self.camera;
+            let terrain_tool = &mut self.terrain_tool;
+            let brush_radius = &mut self.brush_radius;
+            let save_snapshot = &mut save_snapshot;
+            let load_snapshot = &mut load_snapshot;
+            let snapshot_path = &mut self.snapshot_path;
+            let graphics = &mut graphics;
+            let shadows = &mut self.renderer.shadows;
+            let sampler = &mut sampler;
+            let sample_count = &mut sample_count;
 
             let window = imgui::Window::new(imgui::im_str!("Debug"));
             window
                 .size([400.0, 700.0], imgui::Condition::FirstUseEver)
                 .build(&ui, || {
                     // All bodies
-                    for b in ui_bodies {
+                    for (i, b) in ui_bodies.enumerate() {
                         let g = ui.begin_group();
                         ui.text(imgui::im_str!("Body '{}':", b.name));
                         ui.text(imgui::im_str!("Mass: {:.2} kg", b.mass));
+                        ui.text(imgui::im_str!(
+                            "Centre of Mass: {:.2}, {:.2}, {:.2}",
+                            b.center_of_mass.x,
+                            b.center_of_mass.y,
+                            b.center_of_mass.z
+                        ));
+                        let (moments, _) = utils::symmetric_eigen(b.inertia);
+                        ui.text(imgui::im_str!(
+                            "Principal Moments: {:.2}, {:.2}, {:.2}",
+                            moments.x,
+                            moments.y,
+                            moments.z
+                        ));
+                        ui.text(imgui::im_str!(
+                            "Angular Velocity: {:.4}, {:.4}, {:.4} rad/s",
+                            b.angular_velocity.x * utils::UPDATES_PER_SECOND,
+                            b.angular_velocity.y * utils::UPDATES_PER_SECOND,
+                            b.angular_velocity.z * utils::UPDATES_PER_SECOND
+                        ));
                         ui.text(imgui::im_str!("Radius: {:.2} m", b.radius));
                         ui.text(imgui::im_str!(
                             "Velocity: {:.6} m/s",
@@ -364,6 +473,10 @@ impl State {
                             "Escape Velocity: {:.6} m/s",
                             b.escape_velocity()
                         ));
+                        ui.text(imgui::im_str!(
+                            "Orbital Velocity: {:.6} m/s",
+                            b.calculate_velocity_at_radius(b.radius)
+                        ));
                         ui.text(imgui::im_str!(
                             "Position: {:.2}, {:.2}, {:.2}",
                             b.position.x,
@@ -371,6 +484,50 @@ impl State {
                             b.position.z
                         ));
 
+                        ui.text(imgui::im_str!("Triangles: {}", b.triangle_count()));
+
+                        // Only voxel meshes are built from the density field
+                        if b.mesh_type == MeshType::Voxel {
+                            let mut options = b.mesh_options;
+
+                            ui.text(imgui::im_str!("Mesher:"));
+                            for (k, mesher) in Mesher::ALL.iter().enumerate() {
+                                if k % 2 == 1 {
+                                    ui.same_line(0.0);
+                                }
+                                let label = imgui::im_str!("{}##mesher{}", mesher.name(), i);
+                                ui.radio_button(&label, &mut options.mesher, *mesher);
+                            }
+
+                            ui.text(imgui::im_str!("Normals:"));
+                            for mode in NormalMode::ALL.iter() {
+                                let label = imgui::im_str!("{}##normals{}", mode.name(), i);
+                                ui.radio_button(&label, &mut options.normals.mode, *mode);
+                                ui.same_line(0.0);
+                            }
+                            ui.checkbox(&imgui::im_str!("Weld##{}", i), &mut options.normals.weld);
+
+                            if options != b.mesh_options {
+                                *mesh_request = Some((i, options));
+                            }
+                        }
+
+                        if b.gen.has_biomes() {
+                            let label = imgui::im_str!("Biome Map##{}", i);
+                            if ui.small_button(&label) {
+                                *biome_map_request = Some(i);
+                            }
+                        }
+
+                        for format in ExportFormat::ALL.iter() {
+                            let label = imgui::im_str!("Export {}##{}", format.name(), i);
+                            if ui.small_button(&label) {
+                                *export_request = Some((i, *format));
+                            }
+                            ui.same_line(0.0);
+                        }
+                        ui.new_line();
+
                         ui.spacing();
                         ui.separator();
                         ui.spacing();
@@ -390,52 +547,195 @@ impl State {
                     ui.text(imgui::im_str!("Yaw: {:.2} rad", cam.yaw.0));
 
                     cg.end(&ui);
+
+                    ui.spacing();
+                    ui.separator();
+                    ui.spacing();
+
+                    let tg = ui.begin_group();
+                    ui.text(imgui::im_str!("Terrain Tool:"));
+                    ui.radio_button(imgui::im_str!("None"), terrain_tool, TerrainTool::None);
+                    ui.same_line(0.0);
+                    ui.radio_button(imgui::im_str!("Carve"), terrain_tool, TerrainTool::Carve);
+                    ui.same_line(0.0);
+                    ui.radio_button(imgui::im_str!("Add"), terrain_tool, TerrainTool::Add);
+                    imgui::Slider::new(imgui::im_str!("Brush Radius"))
+                        .range(0.5..=8.0)
+                        .build(&ui, brush_radius);
+
+                    tg.end(&ui);
+
+                    ui.spacing();
+                    ui.separator();
+                    ui.spacing();
+
+                    let sg = ui.begin_group();
+                    ui.text(imgui::im_str!("Snapshot:"));
+                    ui.input_text(imgui::im_str!("Path##snapshot"), snapshot_path)
+                        .resize_buffer(true)
+                        .build();
+                    *save_snapshot = ui.small_button(imgui::im_str!("Save"));
+                    ui.same_line(0.0);
+                    *load_snapshot = ui.small_button(imgui::im_str!("Load"));
+
+                    sg.end(&ui);
+
+                    ui.spacing();
+                    ui.separator();
+                    ui.spacing();
+
+                    let gg = ui.begin_group();
+                    ui.text(imgui::im_str!("Graphics:"));
+                    imgui::Slider::new(imgui::im_str!("Exposure"))
+                        .range(0.1..=8.0)
+                        .build(&ui, &mut graphics.exposure);
+                    ui.text(imgui::im_str!("Tonemapper:"));
+                    for tonemapper in Tonemapper::ALL.iter() {
+                        ui.same_line(0.0);
+                        let label = imgui::im_str!("{}", tonemapper.name());
+                        ui.radio_button(&label, &mut graphics.tonemapper, *tonemapper);
+                    }
+                    imgui::Slider::new(imgui::im_str!("Bloom Threshold"))
+                        .range(0.0..=4.0)
+                        .build(&ui, &mut graphics.bloom_threshold);
+                    imgui::Slider::new(imgui::im_str!("Bloom Intensity"))
+                        .range(0.0..=2.0)
+                        .build(&ui, &mut graphics.bloom_intensity);
+                    ui.checkbox(imgui::im_str!("Eclipse Shadows"), shadows);
+                    ui.text(imgui::im_str!("Texture Filter:"));
+                    for filter in TextureFilter::ALL.iter() {
+                        ui.same_line(0.0);
+                        let label = imgui::im_str!("{}", filter.name());
+                        ui.radio_button(&label, &mut sampler.filter, *filter);
+                    }
+                    ui.text(imgui::im_str!("Anisotropy:"));
+                    for level in SamplerConfig::ANISOTROPY_LEVELS.iter() {
+                        ui.same_line(0.0);
+                        let label = imgui::im_str!("{}x##anisotropy", level);
+                        ui.radio_button(&label, &mut sampler.anisotropy, *level);
+                    }
+                    ui.text(imgui::im_str!("MSAA:"));
+                    for count in sample_counts.iter() {
+                        ui.same_line(0.0);
+                        let label = imgui::im_str!("{}x##msaa", count);
+                        ui.radio_button(&label, sample_count, *count);
+                    }
+
+                    gg.end(&ui);
                 });
+
+            if let Some(map) = &self.biome_map {
+                let close_biome_map = &mut close_biome_map;
+                imgui::Window::new(&imgui::im_str!("Biome Map - {}", map.body_name))
+                    .size([540.0, 420.0], imgui::Condition::FirstUseEver)
+                    .build(&ui, || {
+                        imgui::Image::new(
+                            map.texture_id,
+                            [BIOME_MAP_WIDTH as f32 * 2.0, BIOME_MAP_HEIGHT as f32 * 2.0],
+                        )
+                        .build(&ui);
+
+                        for biome in Biome::ALL.iter() {
+                            let color = biome.color();
+                            ui.text_colored([color.x, color.y, color.z, 1.0], biome.name());
+                        }
+
+                        if ui.small_button(imgui::im_str!("Close")) {
+                            *close_biome_map = true;
+                        }
+                    });
+            }
         }
 
-        // Get a frame
-        let frame = self.swap_chain.get_current_frame()?.output;
-        let mut encoder = self.device.create_command_encoder(&Default::default());
+        // Replace the map that is already being shown
+        if let Some(i) = biome_map_request {
+            let map = Self::create_biome_map(
+                &self.bodies[i],
+                &self.device,
+                &self.queue,
+                &mut self.gui_renderer,
+            );
 
-        // ---- Main ---- //
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Main Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
-                    attachment: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
+            if let Some(old) = std::mem::replace(&mut self.biome_map, map) {
+                self.gui_renderer.textures.remove(old.texture_id);
+            }
+        }
+
+        if close_biome_map {
+            if let Some(map) = self.biome_map.take() {
+                self.gui_renderer.textures.remove(map.texture_id);
+            }
+        }
 
-            // Render bodies
-            render_pass.set_pipeline(&self.c_body_pipeline);
-            render_pass.set_bind_group(1, &self.camera.uniform_buffer.bind_group, &[]);
-            render_pass.set_bind_group(3, &self.lights.bind_group, &[]);
+        if graphics != self.renderer.post_process.settings() {
+            self.renderer
+                .post_process
+                .set_settings(graphics, &self.queue);
+        }
+
+        if sample_count != self.renderer.sample_count() {
+            self.renderer
+                .set_sample_count(&self.device, &self.sc_desc, sample_count);
+        }
 
-            for body in self.bodies.iter() {
-                render_pass.set_bind_group(0, &body.texture.bind_group.as_ref().unwrap(), &[]);
-                render_pass.set_bind_group(2, &body.uniform_buffer.bind_group, &[]);
-                render_pass.draw_mesh(&body.mesh);
+        if sampler != self.renderer.sampler {
+            self.renderer.sampler = sampler;
+            for body in self.bodies.iter_mut() {
+                body.material.set_sampler(&self.device, sampler);
             }
         }
 
+        if let Some((i, options)) = mesh_request {
+            self.bodies[i].set_mesh_options(options, &self.worker_pool);
+        }
+
+        if let Some((i, format)) = export_request {
+            let body = &self.bodies[i];
+            match export::export_body(
+                &body.name,
+                &body.gen,
+                body.mesh_type,
+                body.mesh_options,
+                Path::new(export::EXPORT_DIRECTORY),
+                format,
+            ) {
+                Ok(path) => log::info!("Exported '{}' to {}", body.name, path.display()),
+                Err(e) => log::error!("Failed to export '{}': {:?}", body.name, e),
+            }
+        }
+
+        let snapshot_path = Path::new(self.snapshot_path.to_str());
+        if save_snapshot {
+            match snapshot::save_snapshot(snapshot_path, &self.bodies) {
+                Ok(_) => log::info!("Saved snapshot to {}", snapshot_path.display()),
+                Err(e) => log::error!("Failed to save snapshot: {:?}", e),
+            }
+        }
+
+        if load_snapshot {
+            match snapshot::load_snapshot(snapshot_path) {
+                Ok(scenario) => {
+                    self.bodies = Self::create_bodies(
+                        scenario,
+                        &self.device,
+                        &self.queue,
+                        &self.worker_pool,
+                        self.renderer.sampler,
+                    );
+                    log::info!("Loaded snapshot from {}", snapshot_path.display());
+                }
+                Err(e) => log::error!("Failed to load snapshot: {:?}", e),
+            }
+        }
+
+        // Get a frame
+        let frame = self.swap_chain.get_current_frame()?.output;
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+
+        // ---- Main ---- //
+        self.renderer
+            .render(&mut encoder, &frame.view, &self.camera, &self.bodies);
+
         // ---- UI ---- //
         {
             let mut ui_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
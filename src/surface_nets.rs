@@ -0,0 +1,168 @@
+use crate::c_body::{material_color, CBodyGenerator, CHUNK_SIZE};
+use crate::density_grid::{edge_crossing, DensityGrid};
+use crate::utils::Vertex;
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+/// The corners of a cell, as offsets from its lowest corner
+const CORNERS: [[i32; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [0, 1, 0],
+    [1, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [0, 1, 1],
+    [1, 1, 1],
+];
+
+/// The pairs of corners that make up the edges of a cell
+const EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [2, 3],
+    [4, 5],
+    [6, 7],
+    [0, 2],
+    [1, 3],
+    [4, 6],
+    [5, 7],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+/// Build the vertices and indices for a chunk of voxels with surface nets. Each cell that
+/// the surface passes through gets a single vertex at the average of the points where the
+/// surface crosses its edges.
+pub fn build_mesh(gen: &CBodyGenerator, chunk: Vector3<i32>) -> (Vec<Vertex>, Vec<u32>) {
+    build_dual_mesh(gen, chunk, |_, _, crossings| {
+        crossings
+            .iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |sum, p| sum + p)
+            / crossings.len() as f32
+    })
+}
+
+/// Build a mesh with one vertex inside each cell that the surface passes through, joined
+/// by a quad across every edge that the surface crosses. Surface nets and dual contouring
+/// only differ in where the vertex is placed within the cell.
+///
+/// `place` is given the generator, the lowest corner of the cell, and the points where
+/// the surface crosses the edges of the cell.
+pub fn build_dual_mesh<F>(
+    gen: &CBodyGenerator,
+    chunk: Vector3<i32>,
+    place: F,
+) -> (Vec<Vertex>, Vec<u32>)
+where
+    F: Fn(&CBodyGenerator, Vector3<i32>, &[Vector3<f32>]) -> Vector3<f32>,
+{
+    // The quads on the lower edges of this chunk need the cells just below it
+    let origin = chunk * CHUNK_SIZE - Vector3::new(1, 1, 1);
+    let cells = CHUNK_SIZE + 1;
+    let grid = DensityGrid::sample(gen, origin, cells + 1);
+
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut cell_vertices: Vec<Option<u32>> = vec![None; (cells * cells * cells) as usize];
+    let cell_index = |cell: Vector3<i32>| {
+        let local = cell - origin;
+        ((local.x * cells + local.y) * cells + local.z) as usize
+    };
+
+    for x in 0..cells {
+        for y in 0..cells {
+            for z in 0..cells {
+                let cell = origin + Vector3::new(x, y, z);
+                let corners: Vec<Vector3<i32>> = CORNERS
+                    .iter()
+                    .map(|o| cell + Vector3::new(o[0], o[1], o[2]))
+                    .collect();
+                let densities: Vec<f32> = corners.iter().map(|c| grid.get(*c)).collect();
+
+                let crossings: Vec<Vector3<f32>> = EDGES
+                    .iter()
+                    .filter(|[a, b]| (densities[*a] >= 0.0) != (densities[*b] >= 0.0))
+                    .map(|[a, b]| {
+                        edge_crossing(corners[*a], densities[*a], corners[*b], densities[*b])
+                    })
+                    .collect();
+
+                if crossings.is_empty() {
+                    continue;
+                }
+
+                // The surface is coloured by the first solid corner
+                let solid = densities.iter().position(|d| *d >= 0.0).unwrap();
+                let material = gen.get_material(corners[solid].cast().unwrap());
+
+                let position = place(gen, cell, &crossings);
+                let mut vertex = Vertex::with_tex_coords(
+                    position,
+                    gen.get_normal(position),
+                    Vector2::new(0.0, 0.0),
+                );
+                vertex.color = material_color(material);
+
+                cell_vertices[cell_index(cell)] = Some(vertices.len() as u32);
+                vertices.push(vertex);
+            }
+        }
+    }
+
+    // Each edge belongs to the chunk that its lower point is in. The axes after each axis
+    // are in order, so the quads wind counter-clockwise around it.
+    let axes = [
+        Vector3::new(1, 0, 0),
+        Vector3::new(0, 1, 0),
+        Vector3::new(0, 0, 1),
+    ];
+
+    let mut indices: Vec<u32> = Vec::new();
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let point = chunk * CHUNK_SIZE + Vector3::new(x, y, z);
+                let inside = grid.get(point) >= 0.0;
+
+                for (a, axis) in axes.iter().enumerate() {
+                    if (grid.get(point + axis) >= 0.0) == inside {
+                        continue;
+                    }
+
+                    let u = axes[(a + 1) % 3];
+                    let v = axes[(a + 2) % 3];
+                    let quad = [point - u - v, point - v, point, point - u];
+
+                    let quad: Vec<u32> = quad
+                        .iter()
+                        .filter_map(|cell| cell_vertices[cell_index(*cell)])
+                        .collect();
+                    if quad.len() != 4 {
+                        continue;
+                    }
+
+                    // Split along the shorter diagonal so the triangles are less stretched
+                    let position = |i: usize| vertices[quad[i] as usize].position;
+                    let (first, second) = if (position(0) - position(2)).magnitude2()
+                        <= (position(1) - position(3)).magnitude2()
+                    {
+                        ([0, 1, 2], [0, 2, 3])
+                    } else {
+                        ([0, 1, 3], [1, 2, 3])
+                    };
+
+                    for triangle in [first, second].iter() {
+                        // The quad faces along the axis, flip it if the solid side is ahead
+                        if inside {
+                            indices.extend(triangle.iter().map(|i| quad[*i]));
+                        } else {
+                            indices.extend(triangle.iter().rev().map(|i| quad[*i]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    (vertices, indices)
+}
@@ -0,0 +1,135 @@
+use cgmath::{InnerSpace, Vector3};
+
+/// Scales the cube root of the impact energy into a crater radius. This is tuned
+/// for the units used in this simulation (velocities are per-frame)
+pub const CRATER_SCALE: f32 = 100.0;
+
+/// How thick the ejecta rim is compared to the radius of the crater
+pub const RIM_RATIO: f32 = 0.25;
+
+/// Tools that can be used from the GUI to edit the terrain of a body
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TerrainTool {
+    None,
+    Carve,
+    Add,
+}
+
+/// A modification to the density field of a body. Edits are applied in order on top
+/// of the generated terrain, positive density is solid.
+#[derive(Copy, Clone, Debug)]
+pub enum TerrainEdit {
+    /// Removes all material within a sphere
+    Carve { center: Vector3<f32>, radius: f32 },
+    /// Fills a sphere with material
+    Add { center: Vector3<f32>, radius: f32 },
+    /// Adds a ring of material around a point, used for crater ejecta rims
+    Rim {
+        center: Vector3<f32>,
+        normal: Vector3<f32>,
+        radius: f32,
+        thickness: f32,
+    },
+}
+
+impl TerrainEdit {
+    /// Apply this edit to the density at the provided position
+    pub fn apply(&self, position: Vector3<f32>, density: f32) -> f32 {
+        match *self {
+            TerrainEdit::Carve { center, radius } => {
+                density.min((position - center).magnitude() - radius)
+            }
+            TerrainEdit::Add { center, radius } => {
+                density.max(radius - (position - center).magnitude())
+            }
+            TerrainEdit::Rim {
+                center,
+                normal,
+                radius,
+                thickness,
+            } => {
+                // Distance to a torus lying flat on the surface
+                let offset = position - center;
+                let height = offset.dot(normal);
+                let radial = (offset - normal * height).magnitude() - radius;
+
+                density.max(thickness - (radial * radial + height * height).sqrt())
+            }
+        }
+    }
+
+    /// The axis aligned bounds (min, max) that this edit can affect
+    pub fn bounds(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let (center, extent) = match *self {
+            TerrainEdit::Carve { center, radius } => (center, radius),
+            TerrainEdit::Add { center, radius } => (center, radius),
+            TerrainEdit::Rim {
+                center,
+                radius,
+                thickness,
+                ..
+            } => (center, radius + thickness),
+        };
+
+        let extent = Vector3::new(extent, extent, extent);
+        (center - extent, center + extent)
+    }
+}
+
+/// Work out the edits that an impact creates on the surface of a body. The size of the
+/// crater is set by the kinetic energy of the impactor.
+pub fn crater_from_impact(
+    impact_point: Vector3<f32>,
+    normal: Vector3<f32>,
+    impactor_mass: f32,
+    impact_velocity: f32,
+    min_radius: f32,
+    max_radius: f32,
+) -> Vec<TerrainEdit> {
+    let energy = 0.5 * impactor_mass * impact_velocity * impact_velocity;
+    let radius = (CRATER_SCALE * energy.cbrt())
+        .max(min_radius)
+        .min(max_radius);
+
+    vec![
+        // The rim is added first, so the carve gives it a steep inner wall
+        TerrainEdit::Rim {
+            center: impact_point,
+            normal,
+            radius,
+            thickness: radius * RIM_RATIO,
+        },
+        // Centre the bowl slightly above the surface so it is wider than it is deep
+        TerrainEdit::Carve {
+            center: impact_point + normal * (radius * 0.3),
+            radius,
+        },
+    ]
+}
+
+/// Convert the bounds of an edit into an inclusive range of chunk coordinates. The bounds are
+/// padded by a voxel, as faces on the neighbouring chunk may also change.
+pub fn chunks_in_bounds(
+    bounds: (Vector3<f32>, Vector3<f32>),
+    chunk_size: i32,
+) -> (Vector3<i32>, Vector3<i32>) {
+    let (min, max) = bounds;
+    let padding = Vector3::new(1.0, 1.0, 1.0);
+    let size = chunk_size as f32;
+
+    let min = (min - padding) / size;
+    let max = (max + padding) / size;
+
+    (
+        Vector3::new(
+            min.x.floor() as i32,
+            min.y.floor() as i32,
+            min.z.floor() as i32,
+        ),
+        Vector3::new(
+            max.x.floor() as i32,
+            max.y.floor() as i32,
+            max.z.floor() as i32,
+        ),
+    )
+}
@@ -1,5 +1,156 @@
 use anyhow::*;
-use image::GenericImageView;
+use cgmath::{InnerSpace, Vector3};
+use image::{GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use std::num::NonZeroU8;
+
+/// An image with a float for each channel, so mip levels can be averaged without losing
+/// precision
+type FloatImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+/// What the texels of an image are, which decides the format of its texture and how its
+/// mip levels are averaged
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureKind {
+    /// Colours in sRGB, these are averaged as linear light
+    Color,
+    /// Values such as roughness, which are averaged as they are
+    Data,
+    /// Directions in tangent space, mapped from -1..1 into 0..1. These stay unit length in
+    /// every mip level.
+    Normal,
+}
+
+impl TextureKind {
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            TextureKind::Color => wgpu::TextureFormat::Rgba8UnormSrgb,
+            TextureKind::Data | TextureKind::Normal => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+
+    /// Convert the texels of an image to values that can be averaged
+    fn decode(&self, image: &RgbaImage) -> FloatImage {
+        ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let Rgba(texel) = *image.get_pixel(x, y);
+            let mut value = [0.0; 4];
+            for (channel, byte) in value.iter_mut().zip(texel.iter()) {
+                *channel = *byte as f32 / 255.0;
+            }
+
+            if *self == TextureKind::Color {
+                for channel in value.iter_mut().take(3) {
+                    *channel = srgb_to_linear(*channel);
+                }
+            }
+
+            Rgba(value)
+        })
+    }
+
+    /// Convert averaged values back into the texels of a mip level
+    fn encode(&self, image: &FloatImage) -> RgbaImage {
+        ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let Rgba(mut value) = *image.get_pixel(x, y);
+            match self {
+                TextureKind::Color => {
+                    for channel in value.iter_mut().take(3) {
+                        *channel = linear_to_srgb(*channel);
+                    }
+                }
+                TextureKind::Data => {}
+                TextureKind::Normal => {
+                    // Averaging directions shortens them
+                    let normal = Vector3::new(value[0], value[1], value[2]) * 2.0
+                        - Vector3::new(1.0, 1.0, 1.0);
+                    if normal.magnitude2() > 0.0 {
+                        let normal = normal.normalize() * 0.5 + Vector3::new(0.5, 0.5, 0.5);
+                        value[0] = normal.x;
+                        value[1] = normal.y;
+                        value[2] = normal.z;
+                    }
+                }
+            }
+
+            let mut texel = [0; 4];
+            for (byte, channel) in texel.iter_mut().zip(value.iter()) {
+                *byte = (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+
+            Rgba(texel)
+        })
+    }
+}
+
+/// How textures are sampled between their texels and mip levels
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureFilter {
+    /// The nearest texel of the nearest mip level, which is blocky and shimmers
+    Nearest,
+    /// A blend of the nearest texels, from the nearest mip level
+    Bilinear,
+    /// A blend of the nearest texels, from the two nearest mip levels
+    Trilinear,
+}
+
+impl TextureFilter {
+    pub const ALL: [TextureFilter; 3] = [
+        TextureFilter::Nearest,
+        TextureFilter::Bilinear,
+        TextureFilter::Trilinear,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextureFilter::Nearest => "Nearest",
+            TextureFilter::Bilinear => "Bilinear",
+            TextureFilter::Trilinear => "Trilinear",
+        }
+    }
+}
+
+/// How the textures of bodies are sampled
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SamplerConfig {
+    pub filter: TextureFilter,
+    /// The most samples taken across surfaces seen at a grazing angle, 1 turns
+    /// anisotropic filtering off. This is ignored by devices that don't support it.
+    pub anisotropy: u8,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::Trilinear,
+            anisotropy: 8,
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// The levels of anisotropic filtering that can be used
+    pub const ANISOTROPY_LEVELS: [u8; 5] = [1, 2, 4, 8, 16];
+
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        let (filter, mipmap_filter) = match self.filter {
+            TextureFilter::Nearest => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest),
+            TextureFilter::Bilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest),
+            TextureFilter::Trilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+        };
+
+        // The images of bodies wrap around horizontally, and the vertices along the seam
+        // of a sphere have a u past 1
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter,
+            anisotropy_clamp: NonZeroU8::new(self.anisotropy).filter(|a| a.get() > 1),
+            ..Default::default()
+        })
+    }
+}
 
 /// Represents a texture inside this application
 pub struct Texture {
@@ -13,25 +164,26 @@ impl Texture {
     // The DEPTH texture format used for this application
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    /// Create a texture from bytes
-    pub fn from_bytes(
+    /// Create a texture from a colour image
+    pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        bytes: &[u8],
-        label: &str,
+        img: &image::DynamicImage,
+        label: Option<&str>,
     ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image_with_kind(device, queue, img, label, TextureKind::Color)
     }
 
-    /// Create a texture from an image
-    pub fn from_image(
+    /// Create a texture from an image of colours or other data, such as normal maps. Every
+    /// mip level is generated, halving the image each time.
+    pub fn from_image_with_kind(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        kind: TextureKind,
     ) -> Result<Self> {
-        let rgba = img.as_rgba8().unwrap();
+        let mut level = kind.decode(&img.to_rgba8());
         let dimensions = img.dimensions();
 
         let size = wgpu::Extent3d {
@@ -39,30 +191,96 @@ impl Texture {
             height: dimensions.1,
             depth: 1,
         };
+        let mip_level_count = mip_level_count(dimensions.0, dimensions.1);
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: kind.format(),
             usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
         });
 
-        queue.write_texture(
-            wgpu::TextureCopyView {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            rgba,
-            wgpu::TextureDataLayout {
-                offset: 0,
-                bytes_per_row: 4 * dimensions.0,
-                rows_per_image: dimensions.1,
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                level = image::imageops::resize(
+                    &level,
+                    (level.width() / 2).max(1),
+                    (level.height() / 2).max(1),
+                    image::imageops::FilterType::Triangle,
+                );
+            }
+
+            let texels = kind.encode(&level);
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &texels,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * texels.width(),
+                    rows_per_image: texels.height(),
+                },
+                wgpu::Extent3d {
+                    width: texels.width(),
+                    height: texels.height(),
+                    depth: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = SamplerConfig::default().create_sampler(device);
+
+        // Create the appropriate bind group for the input data
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &Self::create_bind_group_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("texture_bind_group"),
+        });
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            bind_group: Some(bind_group),
+        })
+    }
+
+    /// Create a texture that can be drawn into, then sampled by a later pass
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth: 1,
             },
-            size,
-        );
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
@@ -70,12 +288,11 @@ impl Texture {
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
 
-        // Create the appropriate bind group for the input data
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &Self::create_bind_group_layout(device),
             entries: &[
@@ -88,7 +305,137 @@ impl Texture {
                     resource: wgpu::BindingResource::Sampler(&sampler),
                 },
             ],
-            label: Some("texture_bind_group"),
+            label: Some("render_target_bind_group"),
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+            bind_group: Some(bind_group),
+        }
+    }
+
+    /// Create a texture that is drawn into with several samples for each pixel. It can't be
+    /// sampled, it is resolved into another texture instead.
+    pub fn create_multisampled_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            sampler,
+            bind_group: None,
+        }
+    }
+
+    /// Create a cube map from the images of its six faces, in the order +X, -X, +Y, -Y,
+    /// +Z, -Z. Every face must be the same square size.
+    pub fn from_cube_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: &str,
+    ) -> Result<Self> {
+        if faces.len() != 6 {
+            bail!("A cube map needs 6 faces, {} were provided", faces.len());
+        }
+
+        let (width, height) = faces[0].dimensions();
+        if width != height || faces.iter().any(|f| f.dimensions() != (width, height)) {
+            bail!("The faces of a cube map must be square and the same size");
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+
+        // Each face is a layer of the texture
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                },
+                &face.to_rgba8(),
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * width,
+                    rows_per_image: height,
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &Self::create_cube_bind_group_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("cube_texture_bind_group"),
         });
 
         Ok(Self {
@@ -104,6 +451,7 @@ impl Texture {
     pub fn create_depth_texture(
         device: &wgpu::Device,
         sc_desc: &wgpu::SwapChainDescriptor,
+        sample_count: u32,
         label: &str,
     ) -> Self {
         // Size of depth texture should match the swap chain descriptor
@@ -118,7 +466,8 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            // This must match the colour targets it is drawn with
+            sample_count,
             dimension: wgpu::TextureDimension::D2, // 2D texture
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
@@ -181,4 +530,55 @@ impl Texture {
 
         texture_bind_group_layout
     }
+
+    /// The layout of the bind group of a cube map
+    pub fn create_cube_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("cube_texture_bind_group_layout"),
+        })
+    }
+}
+
+/// How many mip levels a texture of a size has, down to a single texel
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// The light of an sRGB channel, both are 0..1
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The sRGB channel for an amount of light, both are 0..1
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
 }
@@ -1,9 +1,12 @@
+use crate::atmosphere::AtmosphereConfig;
+use crate::material::MaterialConfig;
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct CameraUniform {
     pub view_proj: cgmath::Matrix4<f32>, // 4x4 matrix
+    pub view_position: cgmath::Vector4<f32>,
 }
 
 unsafe impl bytemuck::Zeroable for CameraUniform {}
@@ -13,30 +16,216 @@ unsafe impl bytemuck::Pod for CameraUniform {}
 #[derive(Copy, Clone, Debug)]
 pub struct ModelUniform {
     pub model: cgmath::Matrix4<f32>, // 4x4 matrix
+    /// The colour of the light given off by the surface
+    pub emission_color: cgmath::Vector3<f32>,
+    /// How bright the surface glows, past 1 it blooms. Bodies that glow are not lit by
+    /// others, such as stars, 0 is lit as normal.
+    pub emission: f32,
 }
 
 unsafe impl bytemuck::Zeroable for ModelUniform {}
 unsafe impl bytemuck::Pod for ModelUniform {}
 
-// main.rs
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
-pub struct LightUniform {
+pub struct MaterialUniform {
+    pub roughness: f32,
+    pub metallic: f32,
+    pub emissive: f32,
+    // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
+    _padding: f32,
+}
+
+unsafe impl bytemuck::Zeroable for MaterialUniform {}
+unsafe impl bytemuck::Pod for MaterialUniform {}
+
+impl MaterialUniform {
+    pub fn new(config: &MaterialConfig) -> Self {
+        Self {
+            roughness: config.roughness,
+            metallic: config.metallic,
+            emissive: config.emissive,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// The most lights that can shine on the scene at once, this must match the shaders
+pub const MAX_LIGHTS: usize = 4;
+
+/// The most bodies that can cast shadows at once, this must match the shaders
+pub const MAX_OCCLUDERS: usize = 16;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct PointLight {
+    pub position: cgmath::Vector3<f32>,
+    /// The brightness of the light one unit away, it falls off with the square of the
+    /// distance
+    pub intensity: f32,
+    pub color: cgmath::Vector3<f32>,
+    /// The radius of the star the light comes from, larger lights cast softer shadows
+    pub radius: f32,
+}
+
+unsafe impl bytemuck::Zeroable for PointLight {}
+unsafe impl bytemuck::Pod for PointLight {}
+
+impl PointLight {
+    pub fn new(
+        position: cgmath::Vector3<f32>,
+        color: cgmath::Vector3<f32>,
+        intensity: f32,
+        radius: f32,
+    ) -> Self {
+        Self {
+            position,
+            intensity,
+            color,
+            radius,
+        }
+    }
+}
+
+/// A body that can block light, as a sphere
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Occluder {
     pub position: cgmath::Vector3<f32>,
+    pub radius: f32,
+    /// Points closer than this are on the body itself, so it doesn't shadow them
+    pub bounds: f32,
     // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
+    _padding: [u32; 3],
+}
+
+unsafe impl bytemuck::Zeroable for Occluder {}
+unsafe impl bytemuck::Pod for Occluder {}
+
+impl Occluder {
+    pub fn new(position: cgmath::Vector3<f32>, radius: f32, bounds: f32) -> Self {
+        Self {
+            position,
+            radius,
+            bounds,
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct LightUniform {
+    pub lights: [PointLight; MAX_LIGHTS],
+    pub occluders: [Occluder; MAX_OCCLUDERS],
+    /// How many of the lights are used
+    pub light_count: u32,
+    pub occluder_count: u32,
+    /// 1 if bodies cast shadows on each other
+    pub shadows: u32,
     _padding: u32,
-    pub color: cgmath::Vector3<f32>,
 }
 
 unsafe impl bytemuck::Zeroable for LightUniform {}
 unsafe impl bytemuck::Pod for LightUniform {}
 
 impl LightUniform {
-    pub fn new(position: cgmath::Vector3<f32>, color: cgmath::Vector3<f32>) -> Self {
-        Self {
-            position,
+    /// Fill the uniform with up to `MAX_LIGHTS` lights and `MAX_OCCLUDERS` occluders, the
+    /// rest are ignored
+    pub fn new(lights: &[PointLight], occluders: &[Occluder], shadows: bool) -> Self {
+        let zero = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        let mut uniform = Self {
+            lights: [PointLight::new(zero, zero, 0.0, 0.0); MAX_LIGHTS],
+            occluders: [Occluder::new(zero, 0.0, 0.0); MAX_OCCLUDERS],
+            light_count: lights.len().min(MAX_LIGHTS) as u32,
+            occluder_count: occluders.len().min(MAX_OCCLUDERS) as u32,
+            shadows: shadows as u32,
             _padding: 0,
-            color,
+        };
+
+        for (slot, light) in uniform.lights.iter_mut().zip(lights.iter()) {
+            *slot = *light;
+        }
+
+        for (slot, occluder) in uniform.occluders.iter_mut().zip(occluders.iter()) {
+            *slot = *occluder;
+        }
+
+        uniform
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct PostProcessUniform {
+    pub exposure: f32,
+    /// How bright a pixel needs to be before it blooms
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// 0 for ACES, 1 for Reinhard
+    pub tonemapper: u32,
+}
+
+unsafe impl bytemuck::Zeroable for PostProcessUniform {}
+unsafe impl bytemuck::Pod for PostProcessUniform {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BlurUniform {
+    /// The distance between samples, in texture coordinates
+    pub direction: cgmath::Vector2<f32>,
+    // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
+    _padding: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for BlurUniform {}
+unsafe impl bytemuck::Pod for BlurUniform {}
+
+impl BlurUniform {
+    pub fn new(direction: cgmath::Vector2<f32>) -> Self {
+        Self {
+            direction,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct AtmosphereUniform {
+    pub rayleigh: cgmath::Vector3<f32>,
+    pub rayleigh_scale_height: f32,
+    pub mie: f32,
+    pub mie_scale_height: f32,
+    pub mie_anisotropy: f32,
+    pub surface_radius: f32,
+    pub atmosphere_radius: f32,
+    /// The radius of the mesh the atmosphere is drawn on
+    pub shell_radius: f32,
+    // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
+    _padding: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for AtmosphereUniform {}
+unsafe impl bytemuck::Pod for AtmosphereUniform {}
+
+impl AtmosphereUniform {
+    pub fn new(
+        config: &AtmosphereConfig,
+        surface_radius: f32,
+        atmosphere_radius: f32,
+        shell_radius: f32,
+    ) -> Self {
+        Self {
+            rayleigh: config.rayleigh,
+            rayleigh_scale_height: config.rayleigh_scale_height,
+            mie: config.mie,
+            mie_scale_height: config.mie_scale_height,
+            mie_anisotropy: config.mie_anisotropy,
+            surface_radius,
+            atmosphere_radius,
+            shell_radius,
+            _padding: [0.0; 2],
         }
     }
 }
@@ -9,6 +9,10 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 /// This custom universe uses this G
 pub const G: f32 = 1.0e-7;
 
+/// Velocities in this universe are measured per update. Rotation follows the time that
+/// has actually passed, counted in updates at this rate.
+pub const UPDATES_PER_SECOND: f32 = 60.0;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
@@ -16,6 +20,7 @@ pub struct Vertex {
     pub color: cgmath::Vector3<f32>,
     pub tex_coord: cgmath::Vector2<f32>,
     pub normal: cgmath::Vector3<f32>,
+    pub tangent: cgmath::Vector3<f32>,
 }
 
 unsafe impl bytemuck::Zeroable for Vertex {}
@@ -23,13 +28,13 @@ unsafe impl bytemuck::Pod for Vertex {}
 
 impl Vertex {
     /// Create a vertex with color
-    #[allow(dead_code)]
     pub fn with_color(position: cgmath::Vector3<f32>, color: cgmath::Vector3<f32>) -> Self {
         Vertex {
             position,
             color,
             tex_coord: cgmath::Vector2::new(0.0, 0.0),
             normal: cgmath::Vector3::new(0.0, 0.0, 0.0),
+            tangent: cgmath::Vector3::new(0.0, 0.0, 0.0),
         }
     }
 
@@ -44,6 +49,23 @@ impl Vertex {
             color: cgmath::Vector3::new(0.0, 0.0, 0.0),
             tex_coord,
             normal,
+            tangent: cgmath::Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Create a vertex with tex coords and a tangent
+    pub fn with_tangent(
+        position: cgmath::Vector3<f32>,
+        normal: cgmath::Vector3<f32>,
+        tex_coord: cgmath::Vector2<f32>,
+        tangent: cgmath::Vector3<f32>,
+    ) -> Self {
+        Vertex {
+            position,
+            color: cgmath::Vector3::new(0.0, 0.0, 0.0),
+            tex_coord,
+            normal,
+            tangent,
         }
     }
 
@@ -75,7 +97,57 @@ impl Vertex {
                     shader_location: 3,
                     format: wgpu::VertexFormat::Float3,
                 },
+                wgpu::VertexAttribute {
+                    offset: (std::mem::size_of::<cgmath::Vector3<f32>>() * 3
+                        + std::mem::size_of::<cgmath::Vector2<f32>>())
+                        as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float3,
+                },
             ],
         }
     }
 }
+
+/// Find the eigenvalues and eigenvectors of a symmetric matrix with Jacobi rotations.
+/// Each column of the returned matrix is the eigenvector of the matching eigenvalue.
+pub fn symmetric_eigen(
+    matrix: cgmath::Matrix3<f32>,
+) -> (cgmath::Vector3<f32>, cgmath::Matrix3<f32>) {
+    use cgmath::SquareMatrix;
+
+    let mut a = matrix;
+    let mut vectors = cgmath::Matrix3::identity();
+
+    for _ in 0..8 {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)].iter().copied() {
+            if a[p][q].abs() < 1e-9 {
+                continue;
+            }
+
+            // Rotate in the plane of p and q, so that the element at (p, q) becomes zero
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            for k in 0..3 {
+                let (akp, akq) = (a[k][p], a[k][q]);
+                a[k][p] = c * akp - s * akq;
+                a[k][q] = s * akp + c * akq;
+            }
+            for k in 0..3 {
+                let (apk, aqk) = (a[p][k], a[q][k]);
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for k in 0..3 {
+                let (vpk, vqk) = (vectors[p][k], vectors[q][k]);
+                vectors[p][k] = c * vpk - s * vqk;
+                vectors[q][k] = s * vpk + c * vqk;
+            }
+        }
+    }
+
+    (cgmath::Vector3::new(a[0][0], a[1][1], a[2][2]), vectors)
+}
@@ -0,0 +1,310 @@
+use crate::c_body::{is_solid, CBodyGenerator, CHUNK_SIZE, MATERIAL_AIR};
+use crate::generator::{BodyGenerator, GeneratorParameters};
+use anyhow::*;
+use cgmath::Vector3;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Densities are stored as a byte, saturating at this distance from the surface. Only
+/// the densities close to the surface change the shape of the mesh.
+pub const DENSITY_RANGE: f32 = 4.0;
+
+/// How many points are stored for each chunk
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// The longest run that can be stored in a single run length pair
+const MAX_RUN: usize = u8::MAX as usize;
+
+/// The density and material at every point of a chunk, from `position * CHUNK_SIZE` up to
+/// (but not including) the next chunk
+#[derive(Clone)]
+pub struct VoxelChunk {
+    pub position: Vector3<i32>,
+    densities: Vec<i8>,
+    materials: Vec<u8>,
+}
+
+impl VoxelChunk {
+    /// Sample a chunk of the terrain of a body, including any edits
+    pub fn sample(gen: &CBodyGenerator, position: Vector3<i32>) -> Self {
+        let mut densities = Vec::with_capacity(CHUNK_VOLUME);
+        let mut materials = Vec::with_capacity(CHUNK_VOLUME);
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let point = (position * CHUNK_SIZE + Vector3::new(x, y, z))
+                        .cast()
+                        .unwrap();
+
+                    // Air and water are found from the density and sea level, so only the
+                    // material of solid points needs to be kept
+                    let material = gen.get_material(point);
+                    densities.push(quantize_density(gen.get_density(point)));
+                    materials.push(if is_solid(material) {
+                        material
+                    } else {
+                        MATERIAL_AIR
+                    });
+                }
+            }
+        }
+
+        Self {
+            position,
+            densities,
+            materials,
+        }
+    }
+
+    fn index(point: Vector3<i32>) -> usize {
+        ((point.x * CHUNK_SIZE + point.y) * CHUNK_SIZE + point.z) as usize
+    }
+
+    /// Run length encode the densities and materials of the chunk. Most of a chunk is
+    /// either deep inside the body or empty space, so this is much smaller than the grid.
+    pub fn encode(&self, output: &mut Vec<u8>) {
+        for grid in [
+            self.densities.iter().map(|d| *d as u8).collect(),
+            self.materials.clone(),
+        ]
+        .iter()
+        {
+            let encoded = run_length_encode(grid);
+            output.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+            output.extend_from_slice(&encoded);
+        }
+    }
+
+    /// Read a chunk written by `encode`, returning it along with the number of bytes read
+    pub fn decode(position: Vector3<i32>, input: &[u8]) -> Result<(Self, usize)> {
+        let mut offset = 0;
+        let mut grids: Vec<Vec<u8>> = Vec::with_capacity(2);
+
+        for _ in 0..2 {
+            let header = input
+                .get(offset..offset + 4)
+                .context("Chunk is missing its length")?;
+            let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            offset += 4;
+
+            let encoded = input
+                .get(offset..offset + length)
+                .context("Chunk is shorter than its length")?;
+            grids.push(run_length_decode(encoded, CHUNK_VOLUME)?);
+            offset += length;
+        }
+
+        let materials = grids.pop().unwrap();
+        let densities = grids.pop().unwrap().into_iter().map(|d| d as i8).collect();
+
+        Ok((
+            Self {
+                position,
+                densities,
+                materials,
+            },
+            offset,
+        ))
+    }
+}
+
+/// Store a density in a byte, with the most precision close to the surface. Empty points
+/// never round up to zero, so they don't become solid.
+fn quantize_density(density: f32) -> i8 {
+    let quantized = (density / DENSITY_RANGE * i8::MAX as f32)
+        .round()
+        .clamp(-(i8::MAX as f32), i8::MAX as f32) as i8;
+
+    if density < 0.0 {
+        quantized.min(-1)
+    } else {
+        quantized
+    }
+}
+
+fn dequantize_density(density: i8) -> f32 {
+    density as f32 / i8::MAX as f32 * DENSITY_RANGE
+}
+
+/// Encode bytes as pairs of (run length, value)
+pub fn run_length_encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let value = data[i];
+        let run = data[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|v| **v == value)
+            .count();
+
+        encoded.push(run as u8);
+        encoded.push(value);
+        i += run;
+    }
+
+    encoded
+}
+
+/// Decode bytes written by `run_length_encode`, which must decode to exactly `length` bytes
+pub fn run_length_decode(encoded: &[u8], length: usize) -> Result<Vec<u8>> {
+    let pairs = encoded.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        bail!("Run length data has an odd number of bytes");
+    }
+
+    let mut data = Vec::with_capacity(length);
+    for pair in pairs {
+        data.resize(data.len() + pair[0] as usize, pair[1]);
+    }
+
+    if data.len() != length {
+        bail!(
+            "Run length data decoded to {} bytes, expected {}",
+            data.len(),
+            length
+        );
+    }
+
+    Ok(data)
+}
+
+/// Terrain that was sampled from another generator and stored as voxels, such as a body
+/// loaded from a snapshot. Densities are interpolated between the stored points.
+pub struct VoxelGenerator {
+    /// The parameters of the generator that the voxels were sampled from
+    source: GeneratorParameters,
+    surface_radius: f32,
+    bounds: f32,
+    detail_angle: f32,
+    chunks: HashMap<(i32, i32, i32), VoxelChunk>,
+}
+
+impl VoxelGenerator {
+    pub fn new(
+        source: GeneratorParameters,
+        surface_radius: f32,
+        bounds: f32,
+        detail_angle: f32,
+        chunks: Vec<VoxelChunk>,
+    ) -> Self {
+        Self {
+            source,
+            surface_radius,
+            bounds,
+            detail_angle,
+            chunks: chunks
+                .into_iter()
+                .map(|c| ((c.position.x, c.position.y, c.position.z), c))
+                .collect(),
+        }
+    }
+
+    /// The chunk that a point is in, along with the position of the point in the chunk
+    fn locate(&self, point: Vector3<i32>) -> Option<(&VoxelChunk, Vector3<i32>)> {
+        let chunk = point.map(|v| v.div_euclid(CHUNK_SIZE));
+        self.chunks
+            .get(&(chunk.x, chunk.y, chunk.z))
+            .map(|c| (c, point - chunk * CHUNK_SIZE))
+    }
+
+    /// The density at a stored point, anything that was not stored is empty space
+    fn point_density(&self, point: Vector3<i32>) -> f32 {
+        match self.locate(point) {
+            Some((chunk, local)) => dequantize_density(chunk.densities[VoxelChunk::index(local)]),
+            None => -DENSITY_RANGE,
+        }
+    }
+}
+
+impl BodyGenerator for VoxelGenerator {
+    fn density(&self, position: Vector3<f32>) -> f32 {
+        // Trilinear interpolation between the eight surrounding points
+        let base = position.map(f32::floor);
+        let t = position - base;
+        let base: Vector3<i32> = base.cast().unwrap();
+
+        let mut density = 0.0;
+        for corner in 0..8 {
+            let offset = Vector3::new(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+            let weight = (if offset.x == 1 { t.x } else { 1.0 - t.x })
+                * (if offset.y == 1 { t.y } else { 1.0 - t.y })
+                * (if offset.z == 1 { t.z } else { 1.0 - t.z });
+
+            if weight > 0.0 {
+                density += self.point_density(base + offset) * weight;
+            }
+        }
+
+        density
+    }
+
+    fn material(&self, position: Vector3<f32>) -> u8 {
+        // Between the stored points the density can be solid next to an empty point, so
+        // fall back to any solid point around the position
+        let nearest: Vector3<i32> = position.map(f32::round).cast().unwrap();
+        let base: Vector3<i32> = position.map(f32::floor).cast().unwrap();
+        let corners = (0..8).map(|c| base + Vector3::new(c & 1, (c >> 1) & 1, (c >> 2) & 1));
+
+        std::iter::once(nearest)
+            .chain(corners)
+            .filter_map(|p| self.locate(p))
+            .map(|(chunk, local)| chunk.materials[VoxelChunk::index(local)])
+            .find(|m| *m != MATERIAL_AIR)
+            .unwrap_or(MATERIAL_AIR)
+    }
+
+    fn surface_radius(&self) -> f32 {
+        self.surface_radius
+    }
+
+    fn bounds(&self) -> f32 {
+        self.bounds
+    }
+
+    fn detail_angle(&self) -> f32 {
+        self.detail_angle
+    }
+
+    fn parameters(&self) -> GeneratorParameters {
+        self.source.clone()
+    }
+}
+
+impl fmt::Debug for VoxelGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VoxelGenerator")
+            .field("source", &self.source)
+            .field("chunks", &self.chunks.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_length_round_trip_at_the_longest_run() {
+        // The length of a run of one value, followed by a run of another, and how many
+        // runs they are encoded as. Runs longer than a byte can count are split.
+        for (length, runs) in [(1, 2), (254, 2), (255, 2), (256, 3), (510, 3), (511, 4)].iter() {
+            let mut data = vec![7; *length];
+            data.push(3);
+
+            let encoded = run_length_encode(&data);
+            assert_eq!(run_length_decode(&encoded, data.len()).unwrap(), data);
+            assert_eq!(encoded.len(), runs * 2);
+            assert_eq!(encoded[0] as usize, (*length).min(MAX_RUN));
+        }
+    }
+
+    #[test]
+    fn run_length_decode_checks_the_length() {
+        let encoded = run_length_encode(&[1; 300]);
+        assert!(run_length_decode(&encoded, 299).is_err());
+        assert!(run_length_decode(&encoded[..encoded.len() - 1], 300).is_err());
+    }
+}
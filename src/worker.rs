@@ -0,0 +1,88 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// How many threads a pool has when the number of cores can't be found. Most machines
+/// have at least this many cores besides the one the main thread runs on.
+const DEFAULT_THREADS: usize = 3;
+
+/// A fixed set of threads that run jobs in the background. Work that touches the GPU
+/// still needs to happen on the main thread, so jobs should send their results back
+/// over a channel.
+pub struct WorkerPool {
+    sender: Option<Sender<Job>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Create a pool with a thread for every core but the one the main thread runs on
+    pub fn new() -> Self {
+        let count = thread::available_parallelism()
+            .map(|cores| cores.get().saturating_sub(1).max(1))
+            .unwrap_or(DEFAULT_THREADS);
+        Self::with_threads(count)
+    }
+
+    /// Create a pool with the provided number of threads
+    pub fn with_threads(count: usize) -> Self {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let threads = (0..count)
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("Eris Worker {}", i))
+                    .spawn(move || Self::run(receiver))
+                    .expect("Failed to spawn worker thread!")
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            threads,
+        }
+    }
+
+    /// Queue a job to run on the next free thread
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(sender) = &self.sender {
+            // The threads only stop once the sender is dropped, so this can't fail
+            sender.send(Box::new(job)).unwrap();
+        }
+    }
+
+    fn run(receiver: Arc<Mutex<Receiver<Job>>>) {
+        loop {
+            // Only hold the lock while waiting, not while running the job
+            let job = receiver.lock().unwrap().recv();
+            match job {
+                Ok(job) => job(),
+                // The pool has been dropped
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Dropping the sender stops the threads once they finish their current job
+        self.sender.take();
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}